@@ -0,0 +1,348 @@
+//! Content addressed cache of compressed images, shared by every checkout on a machine. An entry
+//! is a directory named after the hash of the source image and its compression settings, holding
+//! one file per output extension (`ktx`, or `dxt1`/`dxt4` and `json`)
+
+use std::{
+    env::var_os,
+    fs::{copy, create_dir_all, read_dir, remove_dir_all, rename, File},
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::SystemTime,
+};
+
+use log::{trace, warn};
+
+use crate::LocalError;
+
+/// Name of the file kept in every entry, with the extension of the output it was copied from
+const ENTRY_FILE_STEM: &str = "output";
+/// Marks the directories entries are assembled in before they are renamed into place
+const TEMPORARY_ENTRY_MARKER: &str = ".tmp-";
+
+static TEMPORARY_ENTRY_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) struct Cache {
+    directory: PathBuf,
+    max_size: u64,
+}
+
+struct CacheEntry {
+    path: PathBuf,
+    size: u64,
+    last_used: SystemTime,
+}
+
+/// What a prune removed
+pub(crate) struct PruneSummary {
+    pub removed_entries: usize,
+    pub removed_bytes: u64,
+    pub remaining_bytes: u64,
+}
+
+impl Cache {
+    /// Opens the cache in `directory`, creating it if needed. `max_size` is in bytes
+    pub fn open(directory: &Path, max_size: u64) -> Result<Self, LocalError> {
+        create_dir_all(directory).map_err(|err| {
+            format!(
+                "Could not create the cache directory {}: {err}",
+                directory.display()
+            )
+        })?;
+        Ok(Self {
+            directory: directory.to_path_buf(),
+            max_size,
+        })
+    }
+
+    /// `source_path` is given when it ends up in the output, as the `SourcePath` metadata does,
+    /// so identical images at different paths do not share an entry
+    pub fn key(source_hash: &str, config_hash: &str, source_path: Option<&Path>) -> String {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(source_hash.as_bytes());
+        hasher.update(config_hash.as_bytes());
+        if let Some(source_path) = source_path {
+            hasher.update(source_path.as_os_str().as_encoded_bytes());
+        }
+        hasher.finalize().to_hex().to_string()
+    }
+
+    /// Copies every file of the entry for `key` to the path `destination` gives for its
+    /// extension, and returns the destinations. `None` when the entry does not exist
+    pub fn restore(
+        &self,
+        key: &str,
+        destination: impl Fn(&str) -> Result<PathBuf, LocalError>,
+    ) -> Result<Option<Vec<PathBuf>>, LocalError> {
+        let entry_path = self.directory.join(key);
+        let Ok(entry) = read_dir(&entry_path) else {
+            return Ok(None);
+        };
+        let now = SystemTime::now();
+        let mut restored_paths = vec![];
+        for file in entry {
+            let cached_path = file?.path();
+            let Some(extension) = cached_path
+                .extension()
+                .and_then(|extension| extension.to_str())
+            else {
+                continue;
+            };
+            let destination_path = destination(extension)?;
+            copy(&cached_path, &destination_path)?;
+            // Modification times order entries for eviction, so a hit marks the entry as used
+            File::options()
+                .write(true)
+                .open(&cached_path)?
+                .set_modified(now)?;
+            restored_paths.push(destination_path);
+        }
+        trace!("Restored {key} from the cache");
+        Ok(Some(restored_paths))
+    }
+
+    /// Stores `output_paths` under `key`. The entry is assembled next to its final location and
+    /// renamed into place, so concurrent processes never see half written entries
+    pub fn store(&self, key: &str, output_paths: &[PathBuf]) -> Result<(), LocalError> {
+        let entry_path = self.directory.join(key);
+        if entry_path.exists() {
+            return Ok(());
+        }
+        let temporary_path = self.directory.join(format!(
+            "{key}{TEMPORARY_ENTRY_MARKER}{}-{}",
+            process::id(),
+            TEMPORARY_ENTRY_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        create_dir_all(&temporary_path)?;
+        let result = output_paths.iter().try_for_each(|output_path| {
+            let extension = output_path
+                .extension()
+                .ok_or_else(|| format!("Output {} has no extension", output_path.display()))?;
+            let cached_path = temporary_path
+                .join(ENTRY_FILE_STEM)
+                .with_extension(extension);
+            copy(output_path, cached_path)?;
+            Ok::<_, LocalError>(())
+        });
+        // Losing the race to another process storing the same entry is fine
+        let result = result.and_then(|_| {
+            if rename(&temporary_path, &entry_path).is_err() && !entry_path.exists() {
+                return Err(
+                    format!("Could not move the cache entry to {}", entry_path.display()).into(),
+                );
+            }
+            Ok(())
+        });
+        if temporary_path.exists() {
+            remove_dir_all(&temporary_path)?;
+        }
+        result
+    }
+
+    /// Evicts the least recently used entries until the cache fits in its maximum size
+    pub fn evict(&self) -> Result<PruneSummary, LocalError> {
+        self.prune(self.max_size)
+    }
+
+    /// Evicts the least recently used entries until the cache fits in `max_size` bytes, `0`
+    /// empties it
+    pub fn prune(&self, max_size: u64) -> Result<PruneSummary, LocalError> {
+        let mut entries = self.entries()?;
+        entries.sort_by_key(|entry| entry.last_used);
+        let mut summary = PruneSummary {
+            removed_entries: 0,
+            removed_bytes: 0,
+            remaining_bytes: entries.iter().map(|entry| entry.size).sum(),
+        };
+        for entry in entries {
+            if summary.remaining_bytes <= max_size {
+                break;
+            }
+            if let Err(err) = remove_dir_all(&entry.path) {
+                warn!("Could not evict {}: {err}", entry.path.display());
+                continue;
+            }
+            summary.removed_entries += 1;
+            summary.removed_bytes += entry.size;
+            summary.remaining_bytes -= entry.size;
+        }
+        Ok(summary)
+    }
+
+    /// The complete entries of the cache. Entries another process is still storing are left out,
+    /// and so are entries another process evicts while the cache is scanned
+    fn entries(&self) -> Result<Vec<CacheEntry>, LocalError> {
+        let mut entries = vec![];
+        for entry in read_dir(&self.directory)? {
+            let path = entry?.path();
+            let is_temporary = path
+                .file_name()
+                .and_then(|file_name| file_name.to_str())
+                .is_some_and(|file_name| file_name.contains(TEMPORARY_ENTRY_MARKER));
+            if !path.is_dir() || is_temporary {
+                continue;
+            }
+            match entry_usage(&path) {
+                Ok((size, last_used)) => entries.push(CacheEntry {
+                    path,
+                    size,
+                    last_used,
+                }),
+                Err(err) if err.kind() == ErrorKind::NotFound => {
+                    trace!("{} was removed while scanning the cache", path.display());
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// The total size of the files of an entry and the last time one of them was used
+fn entry_usage(entry_path: &Path) -> io::Result<(u64, SystemTime)> {
+    let mut size = 0;
+    let mut last_used = SystemTime::UNIX_EPOCH;
+    for file in read_dir(entry_path)? {
+        let metadata = file?.metadata()?;
+        size += metadata.len();
+        last_used = last_used.max(metadata.modified()?);
+    }
+    Ok((size, last_used))
+}
+
+/// `$XDG_CACHE_HOME/image_compressor`, falling back to `~/.cache/image_compressor` and to
+/// `%LOCALAPPDATA%\image_compressor` on Windows
+pub(crate) fn default_cache_dir() -> Option<PathBuf> {
+    let cache_home = var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .or_else(|| var_os("LOCALAPPDATA").map(PathBuf::from))?;
+    Some(cache_home.join("image_compressor"))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        fs::{create_dir, read, write, File},
+        path::Path,
+        time::{Duration, SystemTime},
+    };
+
+    use super::{Cache, ENTRY_FILE_STEM};
+
+    fn store_entry(cache: &Cache, key: &str, contents: &[u8], output_directory: &Path) {
+        let output_path = output_directory.join(format!("{key}.ktx"));
+        write(&output_path, contents).unwrap();
+        cache.store(key, &[output_path]).unwrap();
+    }
+
+    /// Backdates every file of an entry, as if it was last used `age` ago
+    fn set_last_used(cache_directory: &Path, key: &str, age: Duration) {
+        let cached_path = cache_directory
+            .join(key)
+            .join(ENTRY_FILE_STEM)
+            .with_extension("ktx");
+        File::options()
+            .write(true)
+            .open(cached_path)
+            .unwrap()
+            .set_modified(SystemTime::now() - age)
+            .unwrap();
+    }
+
+    #[test]
+    fn key_only_depends_on_the_source_path_when_given() {
+        let (first_path, second_path) = (Path::new("a/texture.png"), Path::new("b/texture.png"));
+        assert_eq!(
+            Cache::key("source", "config", None),
+            Cache::key("source", "config", None)
+        );
+        assert_ne!(
+            Cache::key("source", "config", None),
+            Cache::key("source", "config", Some(first_path))
+        );
+        assert_ne!(
+            Cache::key("source", "config", Some(first_path)),
+            Cache::key("source", "config", Some(second_path))
+        );
+    }
+
+    #[test]
+    fn restore_copies_what_store_cached() {
+        let (cache_directory, output_directory) =
+            (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let cache = Cache::open(cache_directory.path(), u64::MAX).unwrap();
+        let output_paths = ["texture.dxt4", "texture.json"]
+            .map(|file_name| output_directory.path().join(file_name));
+        write(&output_paths[0], b"blocks").unwrap();
+        write(&output_paths[1], b"{}").unwrap();
+        cache.store("key", &output_paths).unwrap();
+
+        let restore_directory = tempfile::tempdir().unwrap();
+        let mut restored_paths = cache
+            .restore("key", |extension| {
+                Ok(restore_directory
+                    .path()
+                    .join("restored")
+                    .with_extension(extension))
+            })
+            .unwrap()
+            .unwrap();
+        restored_paths.sort();
+        assert_eq!(
+            restored_paths,
+            ["restored.dxt4", "restored.json"]
+                .map(|file_name| restore_directory.path().join(file_name))
+        );
+        assert_eq!(read(&restored_paths[0]).unwrap(), b"blocks");
+        assert_eq!(read(&restored_paths[1]).unwrap(), b"{}");
+
+        assert!(cache
+            .restore("missing", |_| unreachable!())
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn prune_evicts_the_least_recently_used_entries_first() {
+        let (cache_directory, output_directory) =
+            (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let cache = Cache::open(cache_directory.path(), 20).unwrap();
+        for (key, age_in_hours) in [("oldest", 3), ("newest", 1), ("middle", 2)] {
+            store_entry(&cache, key, &[0; 10], output_directory.path());
+            set_last_used(
+                cache_directory.path(),
+                key,
+                Duration::from_secs(age_in_hours * 3600),
+            );
+        }
+
+        let summary = cache.evict().unwrap();
+        assert_eq!(summary.removed_entries, 1);
+        assert_eq!(summary.remaining_bytes, 20);
+        assert!(!cache_directory.path().join("oldest").exists());
+
+        let summary = cache.prune(10).unwrap();
+        assert_eq!(summary.removed_bytes, 10);
+        assert!(!cache_directory.path().join("middle").exists());
+        assert!(cache_directory.path().join("newest").exists());
+    }
+
+    #[test]
+    fn prune_leaves_entries_being_stored_alone() {
+        let (cache_directory, output_directory) =
+            (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let cache = Cache::open(cache_directory.path(), u64::MAX).unwrap();
+        store_entry(&cache, "stored", &[0; 10], output_directory.path());
+        let temporary_path = cache_directory.path().join("storing.tmp-1-0");
+        create_dir(&temporary_path).unwrap();
+        write(temporary_path.join("output.ktx"), [0; 10]).unwrap();
+
+        let summary = cache.prune(0).unwrap();
+        assert_eq!(summary.removed_entries, 1);
+        assert_eq!(summary.remaining_bytes, 0);
+        assert!(temporary_path.exists());
+    }
+}
@@ -1,18 +1,27 @@
-use std::{env::current_dir, fmt::Display, path::PathBuf};
+use std::{
+    borrow::Cow,
+    env::current_dir,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
 
+use globset::{GlobBuilder, GlobMatcher};
 use ktx2_wrapper::{
+    color_space::ColorSpace,
     config::{
         BasisUniversalBasisLZETC1s, BasisUniversalUASTC, CompressionConfig, KTXCompressionConfig,
         KTXCompressionConfigTypes,
     },
     ffi2::KtxPackUastcFlags,
     ktx_types::UastcPackLevelOptions,
+    source_image::Channels,
 };
+use log::warn;
 use serde::{de::Error, Deserialize, Deserializer, Serialize};
 
-use crate::{CompressionTypes, NumberOfThreads};
+use crate::{cache, manifest::MANIFEST_FILE_NAME, CompressionTypes, NumberOfThreads};
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub(crate) struct Config {
     #[serde(
         deserialize_with = "deserialize_from_directory",
@@ -26,8 +35,13 @@ pub(crate) struct Config {
     to_directory: Option<PathBuf>,
     #[serde(default = "default_delete_original_images")]
     delete_original_images: bool,
+    /// Gitignore style patterns relative to `from_directory`
     #[serde(default = "default_ignore_list")]
-    ignore_list: Vec<PathBuf>,
+    ignore_list: Vec<String>,
+    #[serde(default = "default_use_gitignore")]
+    use_gitignore: bool,
+    #[serde(default = "default_use_imagecompressorignore")]
+    use_imagecompressorignore: bool,
     #[serde(default = "default_compression_container")]
     compression_container: CompressionTypes,
     #[serde(default = "default_number_of_threads")]
@@ -38,6 +52,101 @@ pub(crate) struct Config {
     verbose: bool,
     #[serde(default = "default_compression_config")]
     compression_config: CompressionConfig,
+    /// Deprecated, `rules` set the `color_space` of their `compression_config` instead
+    #[serde(default = "default_color_space_overrides")]
+    color_space_overrides: Vec<ColorSpaceOverride>,
+    #[serde(default = "default_rules")]
+    rules: Vec<CompressionRule>,
+    /// Compressed images are cached here and reused by every checkout, `null` disables the cache
+    #[serde(default = "default_cache_dir")]
+    cache_dir: Option<PathBuf>,
+    #[serde(default = "default_cache_max_size_mb")]
+    cache_max_size_mb: u64,
+}
+
+/// Used when no config file is given. The cache defaults match the ones of a config file, so
+/// caching is on either way
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            from_directory: PathBuf::new(),
+            to_directory: None,
+            delete_original_images: false,
+            ignore_list: vec![],
+            use_gitignore: default_use_gitignore(),
+            use_imagecompressorignore: default_use_imagecompressorignore(),
+            compression_container: CompressionTypes::default(),
+            number_of_threads: None,
+            skip_errors: false,
+            verbose: false,
+            compression_config: CompressionConfig::default(),
+            color_space_overrides: vec![],
+            rules: vec![],
+            cache_dir: default_cache_dir(),
+            cache_max_size_mb: default_cache_max_size_mb(),
+        }
+    }
+}
+
+/// Overrides the `color_space` of `compression_config` for the files matching `path`, a glob
+/// relative to `from_directory` like the one of a rule. A matching directory covers every file in
+/// it. Deprecated in favor of `rules`, and cannot be combined with them
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ColorSpaceOverride {
+    path: PathGlob,
+    color_space: ColorSpace,
+}
+
+impl ColorSpaceOverride {
+    fn matches(&self, relative_path: &Path) -> bool {
+        relative_path
+            .ancestors()
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .any(|ancestor| self.path.matcher.is_match(ancestor))
+    }
+}
+
+/// Overrides `compression_config` and/or `compression_container` for the files matching `glob`,
+/// a path relative to `from_directory` where `*` stays within a directory and `**` spans several.
+/// The first matching rule of `rules` applies
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct CompressionRule {
+    glob: PathGlob,
+    #[serde(default)]
+    compression_config: Option<CompressionConfig>,
+    #[serde(default)]
+    compression_container: Option<CompressionTypes>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+struct PathGlob {
+    pattern: String,
+    matcher: GlobMatcher,
+}
+
+impl TryFrom<String> for PathGlob {
+    type Error = globset::Error;
+
+    fn try_from(pattern: String) -> Result<Self, Self::Error> {
+        let matcher = GlobBuilder::new(&pattern)
+            .literal_separator(true)
+            .build()?
+            .compile_matcher();
+        Ok(Self { pattern, matcher })
+    }
+}
+
+impl From<PathGlob> for String {
+    fn from(value: PathGlob) -> Self {
+        value.pattern
+    }
+}
+
+impl Display for CompressionRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "rule {}", self.glob.pattern)
+    }
 }
 
 impl Display for Config {
@@ -53,11 +162,22 @@ impl Config {
     pub fn delete_original_images(&self) -> bool {
         self.delete_original_images
     }
-    pub fn ignore_list(&self) -> &[PathBuf] {
+    pub fn ignore_list(&self) -> &[String] {
         &self.ignore_list
     }
-    pub fn compression_container(&self) -> CompressionTypes {
-        self.compression_container
+    /// Whether `.gitignore` files in the tree are honored
+    pub fn use_gitignore(&self) -> bool {
+        self.use_gitignore
+    }
+    /// Whether `.imagecompressorignore` files in the tree are honored
+    pub fn use_imagecompressorignore(&self) -> bool {
+        self.use_imagecompressorignore
+    }
+    /// The container of a single image, from the first matching rule that sets one
+    pub fn compression_container_for(&self, image_path: &Path) -> CompressionTypes {
+        self.rule_for(image_path)
+            .and_then(|rule| rule.compression_container)
+            .unwrap_or(self.compression_container)
     }
     pub fn skip_errors(&self) -> bool {
         self.skip_errors
@@ -65,8 +185,92 @@ impl Config {
     pub fn verbose(&self) -> bool {
         self.verbose
     }
-    pub fn compression_config(&self) -> &CompressionConfig {
-        &self.compression_config
+    pub fn cache_dir(&self) -> Option<&Path> {
+        self.cache_dir.as_deref()
+    }
+    /// The maximum cache size in bytes
+    pub fn cache_max_size(&self) -> u64 {
+        self.cache_max_size_mb.saturating_mul(1024 * 1024)
+    }
+    /// Where the build manifest is kept, in `to_directory` or next to the sources when images are
+    /// compressed in place
+    pub fn manifest_path(&self) -> PathBuf {
+        self.to_directory
+            .as_deref()
+            .unwrap_or(&self.from_directory)
+            .join(MANIFEST_FILE_NAME)
+    }
+    /// The first rule whose glob matches `image_path`
+    pub fn rule_for(&self, image_path: &Path) -> Option<&CompressionRule> {
+        let relative_path = self.relative_path(image_path);
+        self.rules
+            .iter()
+            .find(|rule| rule.glob.matcher.is_match(relative_path))
+    }
+    fn relative_path<'a>(&self, image_path: &'a Path) -> &'a Path {
+        image_path
+            .strip_prefix(&self.from_directory)
+            .unwrap_or(image_path)
+    }
+    /// Checks `compression_config` and the config of every rule
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.color_space_overrides.is_empty() {
+            if !self.rules.is_empty() {
+                return Err(
+                    "color_space_overrides cannot be combined with rules, set color_space in a rule"
+                        .to_string(),
+                );
+            }
+            warn!(
+                "color_space_overrides is deprecated, set color_space in a rule instead. Override \
+                 paths now match as globs, so a file name only matches next to from_directory \
+                 unless written as **/name"
+            );
+        }
+        if let Err(err) = self.compression_config.validate() {
+            return Err(format!("Invalid compression_config: {err}"));
+        }
+        for rule in &self.rules {
+            match &rule.compression_config {
+                Some(compression_config) => {
+                    if let Err(err) = compression_config.validate() {
+                        return Err(format!("Invalid compression_config of {rule}: {err}"));
+                    }
+                }
+                None if rule.compression_container.is_none() => {
+                    return Err(format!(
+                        "{rule} must set compression_config, compression_container or both"
+                    ));
+                }
+                None => {}
+            }
+        }
+        Ok(())
+    }
+    /// The compression config for a single image, from the first matching rule that sets one. With
+    /// no rules, any matching color space override is applied
+    pub fn compression_config_for(&self, image_path: &Path) -> Cow<'_, CompressionConfig> {
+        let compression_config = self
+            .rule_for(image_path)
+            .and_then(|rule| rule.compression_config.as_ref())
+            .unwrap_or(&self.compression_config);
+        let color_space_override = self
+            .color_space_overrides
+            .iter()
+            .find(|color_space_override| {
+                color_space_override.matches(self.relative_path(image_path))
+            });
+        match color_space_override {
+            Some(color_space_override)
+                if color_space_override.color_space != compression_config.color_space =>
+            {
+                Cow::Owned(CompressionConfig {
+                    color_space: color_space_override.color_space,
+                    ..compression_config.clone()
+                })
+            }
+            _ => Cow::Borrowed(compression_config),
+        }
     }
 }
 
@@ -112,10 +316,18 @@ fn default_delete_original_images() -> bool {
     false
 }
 
-fn default_ignore_list() -> Vec<PathBuf> {
+fn default_ignore_list() -> Vec<String> {
     vec![]
 }
 
+fn default_use_gitignore() -> bool {
+    false
+}
+
+fn default_use_imagecompressorignore() -> bool {
+    true
+}
+
 fn default_compression_container() -> CompressionTypes {
     CompressionTypes::default()
 }
@@ -132,6 +344,22 @@ fn default_verbose() -> bool {
     false
 }
 
+fn default_color_space_overrides() -> Vec<ColorSpaceOverride> {
+    vec![]
+}
+
+fn default_rules() -> Vec<CompressionRule> {
+    vec![]
+}
+
+fn default_cache_dir() -> Option<PathBuf> {
+    cache::default_cache_dir()
+}
+
+fn default_cache_max_size_mb() -> u64 {
+    10 * 1024
+}
+
 fn default_compression_config() -> CompressionConfig {
     let etc1s_config = BasisUniversalBasisLZETC1s {
         thread_count: Some(4),
@@ -142,6 +370,12 @@ fn default_compression_config() -> CompressionConfig {
         config_type: KTXCompressionConfigTypes::BasisUniversalBasisLZETC1s,
         config: KTXCompressionConfig::BasisUniversalBasisLZETC1s(etc1s_config),
         premultiply: Some(true),
+        color_space: ColorSpace::default(),
+        mipmaps: None,
+        supercompression: None,
+        metadata: None,
+        channels: Channels::default(),
+        verify: None,
     }
 }
 
@@ -158,5 +392,147 @@ fn default_uastc_config() -> CompressionConfig {
         config_type: KTXCompressionConfigTypes::BasisUniversalUASTC,
         config: KTXCompressionConfig::BasisUniversalUASTC(uastc_config),
         premultiply: Some(true),
+        color_space: ColorSpace::default(),
+        mipmaps: None,
+        supercompression: None,
+        metadata: None,
+        channels: Channels::default(),
+        verify: None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+
+    use ktx2_wrapper::{color_space::ColorSpace, config::CompressionConfig};
+
+    use super::{
+        default_compression_config, ColorSpaceOverride, CompressionRule, Config, PathGlob,
+    };
+    use crate::CompressionTypes;
+
+    fn rule(glob: &str, compression_config: Option<CompressionConfig>) -> CompressionRule {
+        CompressionRule {
+            glob: PathGlob::try_from(glob.to_string()).unwrap(),
+            compression_config,
+            compression_container: Some(CompressionTypes::DXT),
+        }
+    }
+
+    fn color_space_override(path: &str, color_space: ColorSpace) -> ColorSpaceOverride {
+        ColorSpaceOverride {
+            path: PathGlob::try_from(path.to_string()).unwrap(),
+            color_space,
+        }
+    }
+
+    fn config_with_rules(rules: Vec<CompressionRule>) -> Config {
+        Config {
+            from_directory: PathBuf::from("/assets"),
+            rules,
+            ..Config::default()
+        }
+    }
+
+    fn rule_pattern<'a>(config: &'a Config, image_path: &str) -> Option<&'a str> {
+        config
+            .rule_for(Path::new(image_path))
+            .map(|rule| rule.glob.pattern.as_str())
+    }
+
+    #[test]
+    fn the_first_matching_rule_wins() {
+        let config = config_with_rules(vec![
+            rule("ui/logo.png", None),
+            rule("ui/**", None),
+            rule("**/*.png", None),
+        ]);
+        assert_eq!(
+            rule_pattern(&config, "/assets/ui/logo.png"),
+            Some("ui/logo.png")
+        );
+        assert_eq!(rule_pattern(&config, "/assets/ui/icon.png"), Some("ui/**"));
+        assert_eq!(
+            rule_pattern(&config, "/assets/albedo.png"),
+            Some("**/*.png")
+        );
+        assert_eq!(rule_pattern(&config, "/assets/albedo.jpg"), None);
+    }
+
+    #[test]
+    fn a_single_star_stays_within_a_directory() {
+        let config = config_with_rules(vec![rule("ui/*.png", None), rule("maps/**/*.png", None)]);
+        assert_eq!(
+            rule_pattern(&config, "/assets/ui/logo.png"),
+            Some("ui/*.png")
+        );
+        assert_eq!(rule_pattern(&config, "/assets/ui/icons/close.png"), None);
+        assert_eq!(
+            rule_pattern(&config, "/assets/maps/albedo.png"),
+            Some("maps/**/*.png")
+        );
+        assert_eq!(
+            rule_pattern(&config, "/assets/maps/level/1/albedo.png"),
+            Some("maps/**/*.png")
+        );
+    }
+
+    #[test]
+    fn rules_set_the_color_space() {
+        let srgb_config = CompressionConfig {
+            color_space: ColorSpace::Srgb,
+            ..default_compression_config()
+        };
+        let config = config_with_rules(vec![rule("ui/**", Some(srgb_config))]);
+        let color_space_of = |image_path: &str| {
+            config
+                .compression_config_for(Path::new(image_path))
+                .color_space
+        };
+        assert_eq!(color_space_of("/assets/ui/logo.png"), ColorSpace::Srgb);
+        assert_eq!(color_space_of("/assets/normal.png"), ColorSpace::Linear);
+    }
+
+    #[test]
+    fn color_space_overrides_cannot_be_combined_with_rules() {
+        let mut config = config_with_rules(vec![]);
+        config.color_space_overrides = vec![color_space_override("ui", ColorSpace::Srgb)];
+        assert!(config.validate().is_ok());
+        config.rules = vec![rule("maps/**", None)];
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn color_space_overrides_match_globs_and_directories() {
+        let mut config = config_with_rules(vec![]);
+        config.color_space_overrides = vec![
+            color_space_override("ui/logo.png", ColorSpace::Linear),
+            color_space_override("ui", ColorSpace::Srgb),
+            color_space_override("**/*_albedo.png", ColorSpace::Srgb),
+        ];
+        let color_space_of = |image_path: &str| {
+            config
+                .compression_config_for(Path::new(image_path))
+                .color_space
+        };
+        assert_eq!(color_space_of("/assets/ui/logo.png"), ColorSpace::Linear);
+        assert_eq!(
+            color_space_of("/assets/ui/icons/close.png"),
+            ColorSpace::Srgb
+        );
+        assert_eq!(
+            color_space_of("/assets/rock/rock_albedo.png"),
+            ColorSpace::Srgb
+        );
+        // Neither a file elsewhere with the same name nor a sibling sharing the prefix
+        assert_eq!(
+            color_space_of("/assets/old/ui/logo.png"),
+            ColorSpace::Linear
+        );
+        assert_eq!(
+            color_space_of("/assets/ui_old/logo.png"),
+            ColorSpace::Linear
+        );
     }
 }
@@ -0,0 +1,215 @@
+//! Decides which paths under `from_directory` are skipped. `ignore_list` and the ignore files found
+//! in the tree use gitignore syntax, so negation (`!keep.png`), anchored (`/raw`) and recursive
+//! (`**/raw/**`) patterns all work. `ignore_list` takes precedence over the ignore files, and
+//! ignore files in deeper directories take precedence over the ones above them
+
+use std::{
+    fs::read_dir,
+    path::{Path, PathBuf},
+};
+
+use ignore::{
+    gitignore::{Gitignore, GitignoreBuilder},
+    Match,
+};
+
+use crate::LocalError;
+
+const IGNORE_FILE_NAME: &str = ".imagecompressorignore";
+const GITIGNORE_FILE_NAME: &str = ".gitignore";
+
+pub(crate) struct IgnoreRules {
+    root: PathBuf,
+    ignore_list: Gitignore,
+    /// One matcher per directory holding ignore files, parents before their subdirectories
+    ignore_files: Vec<Gitignore>,
+}
+
+impl IgnoreRules {
+    /// Compiles `ignore_list` relative to `root` and loads the `.gitignore` and
+    /// `.imagecompressorignore` files of the tree, as enabled
+    pub fn new(
+        root: &Path,
+        ignore_list: &[String],
+        use_gitignore: bool,
+        use_imagecompressorignore: bool,
+    ) -> Result<Self, LocalError> {
+        let mut builder = GitignoreBuilder::new(root);
+        for pattern in ignore_list {
+            builder
+                .add_line(None, pattern)
+                .map_err(|err| format!("Invalid ignore_list pattern {pattern}: {err}"))?;
+        }
+        let mut ignore_rules = Self {
+            root: root.to_path_buf(),
+            ignore_list: builder.build()?,
+            ignore_files: vec![],
+        };
+
+        // Lines of later files win, so `.imagecompressorignore` can re-include what git ignores
+        let ignore_file_names = [
+            (GITIGNORE_FILE_NAME, use_gitignore),
+            (IGNORE_FILE_NAME, use_imagecompressorignore),
+        ]
+        .into_iter()
+        .filter_map(|(file_name, enabled)| enabled.then_some(file_name))
+        .collect::<Vec<_>>();
+        if !ignore_file_names.is_empty() {
+            ignore_rules.load_ignore_files(root, &ignore_file_names)?;
+        }
+        Ok(ignore_rules)
+    }
+
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        if !path.starts_with(&self.root) || path == self.root {
+            return false;
+        }
+        if let Some(ignored) = is_match_ignored(&self.ignore_list, path, is_dir) {
+            return ignored;
+        }
+        self.ignore_files
+            .iter()
+            .rev()
+            .filter(|ignore_file| {
+                path.starts_with(ignore_file.path()) && path != ignore_file.path()
+            })
+            .find_map(|ignore_file| is_match_ignored(ignore_file, path, is_dir))
+            .unwrap_or(false)
+    }
+
+    /// Ignored directories are not searched, matching git, which cannot re-include a file whose
+    /// directory is excluded
+    fn load_ignore_files(
+        &mut self,
+        directory: &Path,
+        ignore_file_names: &[&str],
+    ) -> Result<(), LocalError> {
+        let mut builder = GitignoreBuilder::new(directory);
+        let mut has_ignore_file = false;
+        for ignore_file_name in ignore_file_names {
+            let ignore_file_path = directory.join(ignore_file_name);
+            if ignore_file_path.is_file() {
+                if let Some(err) = builder.add(&ignore_file_path) {
+                    return Err(format!("Invalid {}: {err}", ignore_file_path.display()).into());
+                }
+                has_ignore_file = true;
+            }
+        }
+        if has_ignore_file {
+            self.ignore_files.push(builder.build()?);
+        }
+
+        for entry in read_dir(directory)? {
+            let path = entry?.path();
+            if path.is_dir() && !path.ends_with(".git") && !self.is_ignored(&path, true) {
+                self.load_ignore_files(&path, ignore_file_names)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `Some(true)` when the last matching pattern ignores `path` or one of its parents, `Some(false)`
+/// when it is a negation, `None` when no pattern matches
+fn is_match_ignored(gitignore: &Gitignore, path: &Path, is_dir: bool) -> Option<bool> {
+    match gitignore.matched_path_or_any_parents(path, is_dir) {
+        Match::None => None,
+        Match::Ignore(_) => Some(true),
+        Match::Whitelist(_) => Some(false),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        fs::{create_dir_all, write},
+        path::Path,
+    };
+
+    use super::{IgnoreRules, GITIGNORE_FILE_NAME, IGNORE_FILE_NAME};
+
+    fn write_ignore_file(directory: &Path, file_name: &str, contents: &str) {
+        create_dir_all(directory).unwrap();
+        write(directory.join(file_name), contents).unwrap();
+    }
+
+    fn ignore_list(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn negations_re_include_files() {
+        let root = tempfile::tempdir().unwrap();
+        let ignore_rules = IgnoreRules::new(
+            root.path(),
+            &ignore_list(&["*.png", "!keep.png"]),
+            false,
+            true,
+        )
+        .unwrap();
+        assert!(ignore_rules.is_ignored(&root.path().join("albedo.png"), false));
+        assert!(!ignore_rules.is_ignored(&root.path().join("ui/keep.png"), false));
+    }
+
+    #[test]
+    fn leading_slashes_anchor_to_the_root() {
+        let root = tempfile::tempdir().unwrap();
+        let ignore_rules = IgnoreRules::new(
+            root.path(),
+            &ignore_list(&["/raw", "favicon.png"]),
+            false,
+            true,
+        )
+        .unwrap();
+        assert!(ignore_rules.is_ignored(&root.path().join("raw"), true));
+        assert!(ignore_rules.is_ignored(&root.path().join("raw/albedo.png"), false));
+        assert!(!ignore_rules.is_ignored(&root.path().join("ui/raw/albedo.png"), false));
+        assert!(ignore_rules.is_ignored(&root.path().join("ui/favicon.png"), false));
+        assert!(!ignore_rules.is_ignored(root.path(), true));
+    }
+
+    #[test]
+    fn deeper_ignore_files_take_precedence() {
+        let root = tempfile::tempdir().unwrap();
+        write_ignore_file(root.path(), IGNORE_FILE_NAME, "*.png\n");
+        write_ignore_file(&root.path().join("ui"), IGNORE_FILE_NAME, "!*.png\n");
+        let ignore_rules = IgnoreRules::new(root.path(), &[], false, true).unwrap();
+        assert!(ignore_rules.is_ignored(&root.path().join("albedo.png"), false));
+        assert!(!ignore_rules.is_ignored(&root.path().join("ui/logo.png"), false));
+    }
+
+    #[test]
+    fn ignore_list_takes_precedence_over_ignore_files() {
+        let root = tempfile::tempdir().unwrap();
+        write_ignore_file(&root.path().join("ui"), IGNORE_FILE_NAME, "!logo.png\n");
+        let ignore_rules =
+            IgnoreRules::new(root.path(), &ignore_list(&["logo.png"]), false, true).unwrap();
+        assert!(ignore_rules.is_ignored(&root.path().join("ui/logo.png"), false));
+    }
+
+    #[test]
+    fn ignore_files_are_only_read_when_enabled() {
+        let root = tempfile::tempdir().unwrap();
+        write_ignore_file(root.path(), GITIGNORE_FILE_NAME, "*.psd\n");
+        write_ignore_file(root.path(), IGNORE_FILE_NAME, "*.png\n");
+        let psd_path = root.path().join("albedo.psd");
+        let png_path = root.path().join("albedo.png");
+
+        let ignore_rules = IgnoreRules::new(root.path(), &[], false, false).unwrap();
+        assert!(!ignore_rules.is_ignored(&psd_path, false));
+        assert!(!ignore_rules.is_ignored(&png_path, false));
+
+        let ignore_rules = IgnoreRules::new(root.path(), &[], true, true).unwrap();
+        assert!(ignore_rules.is_ignored(&psd_path, false));
+        assert!(ignore_rules.is_ignored(&png_path, false));
+    }
+
+    #[test]
+    fn files_in_ignored_directories_cannot_be_re_included() {
+        let root = tempfile::tempdir().unwrap();
+        write_ignore_file(&root.path().join("raw"), IGNORE_FILE_NAME, "!*.png\n");
+        let ignore_rules =
+            IgnoreRules::new(root.path(), &ignore_list(&["raw/"]), false, true).unwrap();
+        assert!(ignore_rules.is_ignored(&root.path().join("raw/albedo.png"), false));
+    }
+}
@@ -1,35 +1,50 @@
 use std::{
+    collections::HashSet,
     error::Error,
     fmt,
-    fs::{create_dir_all, remove_file, write, DirEntry, File},
+    fs::{create_dir_all, remove_file, write, File},
     io::BufReader,
     ops::Deref,
     path::{Path, PathBuf},
+    str::FromStr,
     sync::{
         atomic::{AtomicUsize, Ordering},
-        Arc,
+        Arc, Mutex,
     },
 };
 
+use cache::Cache;
 use clap::{arg, command, error::ErrorKind, value_parser, ArgAction, ArgMatches, Command};
 use config::Config;
+use ignore_rules::IgnoreRules;
 use image::{io::Reader, DynamicImage};
-use ktx2_wrapper::write_texture_from_image;
-use log::{info, trace};
+use ktx2_wrapper::{
+    alpha::premultiply_rgba8, ktx_types::TranscodeTarget, transcode_file, write_texture_from_image,
+};
+use log::{info, trace, warn};
+use manifest::{hash_config, hash_file, Manifest, ManifestEntry};
 use num_traits::Bounded;
 use rayon::{prelude::*, ThreadPoolBuilder};
 use serde::{Deserialize, Serialize};
 use serde_json::{from_reader, to_string};
 use shared_types::SupportedImages;
-use strum::{EnumIter, EnumString};
+use strum::{EnumIter, EnumString, IntoEnumIterator};
 use texpresso::{Format, Params};
 
+mod cache;
 mod config;
+mod ignore_rules;
+mod manifest;
+mod watch;
 
 type LocalError = Box<dyn Error + Send + Sync>;
 
 const DXT1_EXTENSION: &str = "dxt1";
 const DXT4_EXTENSION: &str = "dxt4";
+const DXT_METADATA_EXTENSION: &str = "json";
+const KTX_EXTENSION: &str = "ktx";
+/// Extensions of the textures the `transcode` subcommand picks up. `compress` writes `.ktx`
+const KTX_EXTENSIONS: [&str; 2] = ["ktx", "ktx2"];
 
 #[allow(clippy::upper_case_acronyms)]
 #[derive(
@@ -41,6 +56,16 @@ enum CompressionTypes {
     KTX,
 }
 
+impl CompressionTypes {
+    /// The function compressing a single image into this container
+    fn converter(self) -> fn(&Path, &Config) -> Result<Vec<PathBuf>, LocalError> {
+        match self {
+            Self::DXT => convert_image_to_dxt,
+            Self::KTX => convert_image_to_ktx,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 struct NumberOfThreads(u8);
 
@@ -83,41 +108,46 @@ impl From<u8> for NumberOfThreads {
     }
 }
 
+/// State shared by every image of a `compress` run
+struct BuildContext {
+    manifest: Mutex<Manifest>,
+    /// Converts every image, whatever the manifest says
+    force: bool,
+    cache: Option<Cache>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct ImageMetadata {
     pub extension: &'static str,
     pub width: usize,
     pub height: usize,
+    /// Whether the color channels were premultiplied by alpha
+    pub premultiplied: bool,
 }
 
-fn premultiply_alpha(mut image: DynamicImage) -> Result<DynamicImage, LocalError> {
-    let rgba = image
-        .as_mut_rgba8()
-        .ok_or("While premultiplying the alpha, could not process image a RGBA".to_string())?;
-    for pixel in rgba.chunks_exact_mut(4) {
-        pixel[0] = ((pixel[0] as u32 * pixel[3] as u32) / 255) as u8;
-        pixel[1] = ((pixel[1] as u32 * pixel[3] as u32) / 255) as u8;
-        pixel[2] = ((pixel[2] as u32 * pixel[3] as u32) / 255) as u8;
-    }
-    Ok(image)
-}
-
-fn handle_ktx_images_conversion(
+fn handle_images_conversion(
     image_paths: &[PathBuf],
     config: &Config,
+    build_context: &BuildContext,
 ) -> Result<(), LocalError> {
     let images_processed = Arc::new(AtomicUsize::new(0));
+    let images_skipped = AtomicUsize::new(0);
     let total_images = image_paths.len();
 
     if config.verbose() {
         println!("Total images to be processed: {total_images}");
     }
 
-    image_paths.par_iter().try_for_each(|path_buf| {
-        let error_occured = if let Err(err) = convert_image_to_ktx(path_buf, config) {
-            (true, Some(err))
-        } else {
-            (false, None)
+    let result = image_paths.par_iter().try_for_each(|path_buf| {
+        let convert = config.compression_container_for(path_buf).converter();
+        let converted = convert_image_if_changed(path_buf, config, build_context, convert);
+        let error_occured = match converted {
+            Ok(true) => (false, None),
+            Ok(false) => {
+                images_skipped.fetch_add(1, Ordering::Relaxed);
+                (false, None)
+            }
+            Err(err) => (true, Some(err)),
         };
         let current_count = images_processed.fetch_add(1, Ordering::Acquire);
         if config.verbose() && total_images > 20 && current_count % (total_images / 20) == 0 {
@@ -137,74 +167,156 @@ fn handle_ktx_images_conversion(
             }
         }
         Ok(())
-    })
+    });
+    if config.verbose() {
+        println!(
+            "Skipped {} unchanged images",
+            images_skipped.load(Ordering::Relaxed)
+        );
+    }
+    result
 }
 
-fn convert_image_to_ktx(image_path: &Path, config: &Config) -> Result<(), LocalError> {
-    trace!("Begin Converting {:?}", image_path);
-    let image_path_out = if let Some(to_directory) = config.to_directory() {
-        let image_path_out =
-            to_directory.join(image_path.strip_prefix(config.from_directory.as_path())?);
-        if !image_path_out.parent().unwrap().exists() {
-            create_dir_all(image_path_out.parent().unwrap())?;
+/// Converts `image_path` with `convert`, or copies its outputs out of the cache, and records them
+/// in the manifest. Unless `force` is set, images the manifest shows were built from the same
+/// source and config are skipped. Returns whether the image was converted
+fn convert_image_if_changed(
+    image_path: &Path,
+    config: &Config,
+    build_context: &BuildContext,
+    convert: fn(&Path, &Config) -> Result<Vec<PathBuf>, LocalError>,
+) -> Result<bool, LocalError> {
+    let source_path = image_path
+        .strip_prefix(config.from_directory.as_path())?
+        .to_path_buf();
+    let source_hash = hash_file(image_path)?;
+    let compression_config = config.compression_config_for(image_path);
+    let config_hash = hash_config(
+        config.compression_container_for(image_path),
+        &compression_config,
+    )?;
+    let writes_source_path = compression_config
+        .metadata
+        .as_ref()
+        .is_some_and(|metadata| metadata.source_path);
+    if !build_context.force
+        && build_context.manifest.lock().unwrap().is_up_to_date(
+            &source_path,
+            &source_hash,
+            &config_hash,
+        )
+    {
+        trace!("Skipping unchanged {:?}", image_path);
+        return Ok(false);
+    }
+
+    if config.verbose() {
+        match config.rule_for(image_path) {
+            Some(rule) => println!("Compressing {} with {rule}", image_path.display()),
+            None => println!(
+                "Compressing {} with the default compression_config",
+                image_path.display()
+            ),
+        }
+    }
+
+    let cache_key = Cache::key(
+        &source_hash,
+        &config_hash,
+        writes_source_path.then_some(image_path),
+    );
+    let restored_paths = build_context.cache.as_ref().and_then(|cache| {
+        cache
+            .restore(&cache_key, |extension| {
+                output_path(image_path, config, extension)
+            })
+            .unwrap_or_else(|err| {
+                warn!(
+                    "Could not restore {} from the cache: {err}",
+                    image_path.display()
+                );
+                None
+            })
+    });
+    let mut output_paths = match restored_paths {
+        Some(restored_paths) => restored_paths,
+        None => {
+            let output_paths = convert(image_path, config)?;
+            if let Some(cache) = &build_context.cache {
+                if let Err(err) = cache.store(&cache_key, &output_paths) {
+                    warn!("Could not cache {}: {err}", image_path.display());
+                }
+            }
+            output_paths
         }
-        Some(image_path_out)
-    } else {
-        None
     };
-    write_texture_from_image(
-        image_path,
-        image_path_out.as_deref(),
-        config.compression_config(),
-        &image_path.try_into()?,
-    )?;
+    output_paths.sort_by_key(|output_path| {
+        output_path
+            .extension()
+            .is_some_and(|extension| extension == DXT_METADATA_EXTENSION)
+    });
+
     if config.delete_original_images() {
         remove_file(image_path)?;
     }
-    trace!("Finish Converting {:?}", image_path);
-    Ok(())
+    build_context.manifest.lock().unwrap().insert(
+        source_path,
+        ManifestEntry {
+            source_hash,
+            config_hash,
+            output_paths,
+        },
+    );
+    Ok(true)
 }
 
-fn handle_dxt_images_conversion(
-    image_paths: &[PathBuf],
-    config: &Config,
-) -> Result<(), LocalError> {
-    let images_processed = Arc::new(AtomicUsize::new(0));
-    let total_images = image_paths.len();
-    if config.verbose() {
-        println!("Total images to be processed: {total_images}");
+/// Where the output of `image_path` with `extension` is written: under `to_directory` when it is
+/// set, next to the source otherwise. DXT metadata always goes next to the source
+fn output_path(image_path: &Path, config: &Config, extension: &str) -> Result<PathBuf, LocalError> {
+    if extension == DXT_METADATA_EXTENSION {
+        return Ok(image_path.with_extension(DXT_METADATA_EXTENSION));
     }
-
-    image_paths.par_iter().try_for_each(|path_buf| {
-        let error_occured = if let Err(err) = convert_image_to_dxt(path_buf, config) {
-            (true, Some(err))
-        } else {
-            (false, None)
-        };
-        let current_count = images_processed.fetch_add(1, Ordering::Acquire);
-        if config.verbose() && total_images > 20 && current_count % (total_images / 20) == 0 {
-            println!(
-                "Processed {}% of images",
-                (current_count * 100 / total_images) + 1
-            );
-        }
-        if error_occured.0 {
-            eprintln!(
-                "Failed to convert {}: {}",
-                path_buf.display(),
-                error_occured.1.as_ref().unwrap()
-            );
-            if !config.skip_errors() {
-                return Err(error_occured.1.unwrap());
-            }
+    let mut output_path = if let Some(to_directory) = config.to_directory() {
+        let output_path =
+            to_directory.join(image_path.strip_prefix(config.from_directory.as_path())?);
+        if !output_path.parent().unwrap().exists() {
+            create_dir_all(output_path.parent().unwrap())?;
         }
-        Ok(())
-    })
+        output_path
+    } else {
+        image_path.to_path_buf()
+    };
+    output_path.set_extension(extension);
+    Ok(output_path)
+}
+
+/// Returns the path of the written texture
+fn convert_image_to_ktx(image_path: &Path, config: &Config) -> Result<Vec<PathBuf>, LocalError> {
+    trace!("Begin Converting {:?}", image_path);
+    let ktx_path_out = output_path(image_path, config, KTX_EXTENSION)?;
+    write_texture_from_image(
+        image_path,
+        Some(&ktx_path_out),
+        &config.compression_config_for(image_path),
+        &image_path.try_into()?,
+    )?;
+    trace!("Finish Converting {:?}", image_path);
+    Ok(vec![ktx_path_out])
 }
 
-fn convert_image_to_dxt(image_path: &Path, config: &Config) -> Result<(), LocalError> {
+/// Returns the paths of the written texture and of its JSON metadata
+fn convert_image_to_dxt(image_path: &Path, config: &Config) -> Result<Vec<PathBuf>, LocalError> {
     trace!("Begin Converting {:?}", image_path);
 
+    let image_type = SupportedImages::try_from(image_path)?;
+    if matches!(image_type, SupportedImages::Hdr | SupportedImages::Exr) {
+        return Err(format!(
+            "{image_type} images cannot be converted to DXT, use a KTX2 config instead: {}",
+            image_path.display()
+        )
+        .into());
+    }
+
     let mut image: DynamicImage = Reader::open(image_path)?.decode()?;
     if !image.color().has_alpha() {
         let mut image_with_alpha = image.to_rgba8();
@@ -221,12 +333,12 @@ fn convert_image_to_dxt(image_path: &Path, config: &Config) -> Result<(), LocalE
     let width = image.width() as usize;
     let height = image.height() as usize;
 
-    let should_premultiply = config.compression_config().premultiply.unwrap_or(true);
-    let image_u8 = if should_premultiply {
-        premultiply_alpha(image)?.as_bytes().to_vec()
-    } else {
-        image.as_bytes().to_vec()
-    };
+    let compression_config = config.compression_config_for(image_path);
+    let should_premultiply = compression_config.premultiply.unwrap_or(true);
+    let mut image_u8 = image.as_bytes().to_vec();
+    if should_premultiply {
+        premultiply_rgba8(&mut image_u8, compression_config.color_space);
+    }
 
     // texpresso requires the output array be presized
     let compressed_image_size = if has_alpha_mask {
@@ -241,41 +353,26 @@ fn convert_image_to_dxt(image_path: &Path, config: &Config) -> Result<(), LocalE
         Format::Bc1.compress(&image_u8, width, height, Params::default(), &mut output);
     }
 
-    let mut dxt_extension_path = if let Some(to_directory) = config.to_directory() {
-        let dxt_extension_path = to_directory
-            .join(image_path.strip_prefix(config.from_directory.as_path())?)
-            .clone();
-        if !dxt_extension_path.parent().unwrap().exists() {
-            create_dir_all(dxt_extension_path.parent().unwrap())?;
-        }
-        dxt_extension_path
-    } else {
-        image_path.to_path_buf()
-    };
     let dxt_extension = if has_alpha_mask {
         DXT4_EXTENSION
     } else {
         DXT1_EXTENSION
     };
-    dxt_extension_path.set_extension(dxt_extension);
+    let dxt_extension_path = output_path(image_path, config, dxt_extension)?;
     write(dxt_extension_path.as_path(), output)?;
 
     let image_metadata = ImageMetadata {
         extension: dxt_extension,
         width,
         height,
+        premultiplied: should_premultiply,
     };
     let json_string = to_string(&image_metadata)?;
-    let mut json_path = image_path.to_path_buf();
-    json_path.set_extension("json");
-    write(json_path, json_string)?;
-
-    if config.delete_original_images() {
-        remove_file(image_path)?;
-    }
+    let json_path = output_path(image_path, config, DXT_METADATA_EXTENSION)?;
+    write(&json_path, json_string)?;
 
     trace!("Finish Converting {:?}", image_path);
-    Ok(())
+    Ok(vec![dxt_extension_path, json_path])
 }
 
 fn has_alpha_mask(image: &DynamicImage) -> Result<bool, LocalError> {
@@ -288,17 +385,30 @@ fn has_alpha_mask(image: &DynamicImage) -> Result<bool, LocalError> {
     Ok(has_alpha_mask)
 }
 
-fn should_ignore_entry(entry: &DirEntry, ignore_list: &[PathBuf]) -> bool {
-    ignore_list.iter().any(|ignore_list_entry| {
-        if ignore_list_entry.extension().is_some() {
-            entry.path().ends_with(ignore_list_entry)
-        } else {
-            entry.path().starts_with(ignore_list_entry)
-        }
+fn find_images(dir_to_walk: &Path, ignore_rules: &IgnoreRules) -> Result<Vec<PathBuf>, LocalError> {
+    find_files(dir_to_walk, Some(ignore_rules), |path| {
+        SupportedImages::is_valid_path(path)
     })
 }
 
-fn find_images(dir_to_walk: &Path, ignore_list: &[PathBuf]) -> Result<Vec<PathBuf>, LocalError> {
+fn find_ktx_textures(dir_to_walk: &Path) -> Result<Vec<PathBuf>, LocalError> {
+    find_files(dir_to_walk, None, |path| {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| {
+                KTX_EXTENSIONS
+                    .iter()
+                    .any(|ktx_extension| extension.eq_ignore_ascii_case(ktx_extension))
+            })
+    })
+}
+
+/// Recursively collects the files under `dir_to_walk` that `is_wanted` accepts
+fn find_files(
+    dir_to_walk: &Path,
+    ignore_rules: Option<&IgnoreRules>,
+    is_wanted: fn(&Path) -> bool,
+) -> Result<Vec<PathBuf>, LocalError> {
     let directory = dir_to_walk.read_dir()?;
 
     let mut image_paths = vec![];
@@ -313,18 +423,18 @@ fn find_images(dir_to_walk: &Path, ignore_list: &[PathBuf]) -> Result<Vec<PathBu
         let path_display = path.display();
         trace!("Begin scanning {path_display}");
 
-        if should_ignore_entry(&entry, ignore_list) {
+        if ignore_rules.is_some_and(|ignore_rules| ignore_rules.is_ignored(&path, path.is_dir())) {
             continue;
         }
 
         if path.is_dir() {
-            image_paths.extend(find_images(path.as_path(), ignore_list)?);
+            image_paths.extend(find_files(path.as_path(), ignore_rules, is_wanted)?);
             continue;
         }
 
         trace!("Finish scanning {path_display}");
 
-        if SupportedImages::is_valid_path(path.as_path()) {
+        if is_wanted(path.as_path()) {
             image_paths.push(path);
         }
     }
@@ -332,10 +442,9 @@ fn find_images(dir_to_walk: &Path, ignore_list: &[PathBuf]) -> Result<Vec<PathBu
     Ok(image_paths)
 }
 
-fn handle_compress_subcommand(arg_matches: &ArgMatches, command: &mut Command) {
-    let instant = std::time::Instant::now();
-    info!("Begin config validation");
-    let mut config = if let Some(config_string_path) = arg_matches.get_one::<String>("config") {
+/// Reads the config passed with -c or --config, or the defaults when there is none
+fn read_config(arg_matches: &ArgMatches, command: &mut Command) -> Config {
+    if let Some(config_string_path) = arg_matches.get_one::<String>("config") {
         let config_path = PathBuf::from(config_string_path);
         if !config_path.exists() {
             command
@@ -374,7 +483,16 @@ fn handle_compress_subcommand(arg_matches: &ArgMatches, command: &mut Command) {
         }
     } else {
         Config::default()
-    };
+    }
+}
+
+fn handle_compress_subcommand(arg_matches: &ArgMatches, command: &mut Command) {
+    let instant = std::time::Instant::now();
+    info!("Begin config validation");
+    let mut config = read_config(arg_matches, command);
+    if let Err(err) = config.validate() {
+        command.error(ErrorKind::InvalidValue, err).exit()
+    }
     info!("Finish config validation");
 
     if let Some(output_dir) = arg_matches.get_one::<String>("dir") {
@@ -436,17 +554,20 @@ fn handle_compress_subcommand(arg_matches: &ArgMatches, command: &mut Command) {
             )
             .exit()
     }
-    let ignore_list = config
-        .ignore_list()
-        .iter()
-        .map(|ignore_list_item| {
-            let mut item_path_buf: PathBuf = ignore_list_item.into();
-            if item_path_buf.extension().is_none() {
-                item_path_buf = config.from_directory.join(item_path_buf);
-            }
-            item_path_buf
-        })
-        .collect::<Vec<PathBuf>>();
+    let ignore_rules = match IgnoreRules::new(
+        &config.from_directory,
+        config.ignore_list(),
+        config.use_gitignore(),
+        config.use_imagecompressorignore(),
+    ) {
+        Ok(ignore_rules) => ignore_rules,
+        Err(err) => command
+            .error(
+                ErrorKind::InvalidValue,
+                format!("Invalid ignore rules: {err}"),
+            )
+            .exit(),
+    };
     if let Some(number_of_threads) = config.number_of_threads {
         if let Err(err) = ThreadPoolBuilder::new()
             .num_threads(*number_of_threads as usize)
@@ -463,7 +584,7 @@ fn handle_compress_subcommand(arg_matches: &ArgMatches, command: &mut Command) {
 
     info!("Finish Directory Validation");
     info!("Begin Directory Scan And Conversion");
-    let image_paths = match find_images(&config.from_directory, &ignore_list) {
+    let image_paths = match find_images(&config.from_directory, &ignore_rules) {
         Ok(image_paths) => image_paths,
         Err(err) => {
             command
@@ -475,31 +596,73 @@ fn handle_compress_subcommand(arg_matches: &ArgMatches, command: &mut Command) {
         }
     };
 
+    let force = arg_matches.get_flag("force");
+    let watch = arg_matches.get_flag("watch");
+    // Deleting a source removes its outputs in watch mode, which would undo every compression
+    if watch && config.delete_original_images() {
+        command
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--watch cannot be combined with delete_original_images",
+            )
+            .exit();
+    }
+    let manifest_path = config.manifest_path();
+    let manifest = match Manifest::load(&manifest_path) {
+        Ok(manifest) => manifest,
+        Err(err) => {
+            warn!("Ignoring the build manifest, every image will be compressed: {err}");
+            Manifest::default()
+        }
+    };
+    let cache = config.cache_dir().and_then(|cache_dir| {
+        Cache::open(cache_dir, config.cache_max_size())
+            .map_err(|err| warn!("Compressing without a cache: {err}"))
+            .ok()
+    });
+    let build_context = BuildContext {
+        manifest: Mutex::new(manifest),
+        force,
+        cache,
+    };
+
     if config.verbose() {
         println!("Beginning compression, compression config is: {config}");
     }
-    match config.compression_container() {
-        CompressionTypes::DXT => {
-            if let Err(err) = handle_dxt_images_conversion(&image_paths, &config) {
-                command
-                    .error(
-                        ErrorKind::InvalidValue,
-                        format!("Error converting images: {err}"),
-                    )
-                    .exit();
-            }
-        }
-        CompressionTypes::KTX => {
-            if let Err(err) = handle_ktx_images_conversion(&image_paths, &config) {
-                command
-                    .error(
-                        ErrorKind::InvalidValue,
-                        format!("Error converting images: {err}"),
-                    )
-                    .exit()
-            }
+    let result = handle_images_conversion(&image_paths, &config, &build_context);
+    if let Some(cache) = &build_context.cache {
+        match cache.evict() {
+            Ok(summary) if config.verbose() && summary.removed_entries > 0 => println!(
+                "Evicted {} cache entries ({} bytes)",
+                summary.removed_entries, summary.removed_bytes
+            ),
+            Ok(_) => {}
+            Err(err) => warn!("Could not evict cache entries: {err}"),
         }
     }
+    // Saved even when a conversion failed, so the images that succeeded are not redone
+    let mut manifest = build_context.manifest.lock().unwrap();
+    let source_paths = image_paths
+        .iter()
+        .filter_map(|image_path| image_path.strip_prefix(&config.from_directory).ok())
+        .collect::<HashSet<_>>();
+    manifest.retain_sources(&source_paths);
+    let saved = manifest.save(&manifest_path);
+    drop(manifest);
+    if let Err(err) = saved {
+        eprintln!(
+            "Failed to save the build manifest {}: {err}",
+            manifest_path.display()
+        );
+    }
+    if let Err(err) = result {
+        command
+            .error(
+                ErrorKind::InvalidValue,
+                format!("Error converting images: {err}"),
+            )
+            .exit();
+    }
     let elapsed_time_message = format!("ELAPSED TIME {:?}", instant.elapsed());
     if config.verbose() {
         println!("{elapsed_time_message}");
@@ -507,6 +670,202 @@ fn handle_compress_subcommand(arg_matches: &ArgMatches, command: &mut Command) {
         trace!("{elapsed_time_message}");
     }
     info!("Finish Directory Scan and Conversion");
+
+    if watch {
+        if let Err(err) = watch::watch(&config, &ignore_rules, &build_context) {
+            command
+                .error(ErrorKind::Io, format!("Error watching for changes: {err}"))
+                .exit();
+        }
+    }
+}
+
+/// Returns whether the texture was transcoded, textures that are not Basis Universal are copied to
+/// `to_directory` unchanged or left alone in place
+fn transcode_ktx_texture(
+    ktx_path: &Path,
+    target: TranscodeTarget,
+    from_directory: &Path,
+    to_directory: Option<&Path>,
+) -> Result<bool, LocalError> {
+    trace!("Begin Transcoding {:?}", ktx_path);
+    let ktx_path_out = if let Some(to_directory) = to_directory {
+        let ktx_path_out = to_directory.join(ktx_path.strip_prefix(from_directory)?);
+        if !ktx_path_out.parent().unwrap().exists() {
+            create_dir_all(ktx_path_out.parent().unwrap())?;
+        }
+        ktx_path_out
+    } else {
+        ktx_path.to_path_buf()
+    };
+    let transcoded = transcode_file(ktx_path, target, &ktx_path_out)?;
+    if transcoded {
+        trace!("Finish Transcoding {:?}", ktx_path);
+    } else {
+        trace!(
+            "Skipping {:?}, it is not a Basis Universal texture",
+            ktx_path
+        );
+    }
+    Ok(transcoded)
+}
+
+fn handle_transcode_subcommand(arg_matches: &ArgMatches, command: &mut Command) {
+    let instant = std::time::Instant::now();
+    let Some(target) = arg_matches.get_one::<TranscodeTarget>("format").copied() else {
+        command
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "Could not find -f or --format which should name the GPU format to transcode to",
+            )
+            .exit();
+    };
+    let Some(directory) = arg_matches.get_one::<String>("dir") else {
+        command
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "Could not find -d or --dir which should have a path to your KTX2 textures",
+            )
+            .exit();
+    };
+    let from_directory = match PathBuf::from(directory).canonicalize() {
+        Ok(canonicalized_path) if canonicalized_path.is_dir() => canonicalized_path,
+        Ok(_) => command
+            .error(
+                ErrorKind::InvalidValue,
+                format!("Directory {directory} is not actually a directory"),
+            )
+            .exit(),
+        Err(err) => command
+            .error(
+                ErrorKind::InvalidValue,
+                format!("Path {directory} could not be canonicalized, it is likely invalid: {err}"),
+            )
+            .exit(),
+    };
+    let to_directory = arg_matches.get_one::<String>("output").map(PathBuf::from);
+    if let Some(to_directory) = &to_directory {
+        if let Err(err) = create_dir_all(to_directory) {
+            command
+                .error(
+                    ErrorKind::Io,
+                    format!(
+                        "Output path {} does not exist, and could not be created: {err}",
+                        to_directory.display()
+                    ),
+                )
+                .exit()
+        }
+    }
+    if let Some(threads) = arg_matches.get_one::<u8>("threads") {
+        let number_of_threads = NumberOfThreads::from(*threads);
+        if let Err(err) = ThreadPoolBuilder::new()
+            .num_threads(*number_of_threads as usize)
+            .build_global()
+        {
+            command
+                .error(
+                    ErrorKind::InvalidValue,
+                    format!("Invalid number of threads: {number_of_threads}: {:?}", err),
+                )
+                .exit();
+        }
+    }
+    let skip_errors = arg_matches.get_flag("skip-errors");
+
+    let ktx_paths = match find_ktx_textures(&from_directory) {
+        Ok(ktx_paths) => ktx_paths,
+        Err(err) => command
+            .error(
+                ErrorKind::InvalidValue,
+                format!("Error finding KTX2 textures: {err}"),
+            )
+            .exit(),
+    };
+    println!("Transcoding {} textures to {target}", ktx_paths.len());
+    let textures_skipped = AtomicUsize::new(0);
+    let result = ktx_paths.par_iter().try_for_each(|ktx_path| {
+        match transcode_ktx_texture(ktx_path, target, &from_directory, to_directory.as_deref()) {
+            Ok(true) => {}
+            Ok(false) => {
+                textures_skipped.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(err) => {
+                eprintln!("Failed to transcode {}: {err}", ktx_path.display());
+                if !skip_errors {
+                    return Err(err);
+                }
+            }
+        }
+        Ok(())
+    });
+    let textures_skipped = textures_skipped.load(Ordering::Relaxed);
+    if textures_skipped > 0 {
+        let skipped_action = if to_directory.is_some() {
+            "copied unchanged"
+        } else {
+            "left unchanged"
+        };
+        println!("{textures_skipped} textures are not Basis Universal and were {skipped_action}");
+    }
+    if let Err(err) = result {
+        command
+            .error(
+                ErrorKind::InvalidValue,
+                format!("Error transcoding textures: {err}"),
+            )
+            .exit();
+    }
+    println!("ELAPSED TIME {:?}", instant.elapsed());
+}
+
+fn handle_cache_subcommand(arg_matches: &ArgMatches, command: &mut Command) {
+    match arg_matches.subcommand() {
+        Some(("prune", sub_matches)) => handle_cache_prune_subcommand(sub_matches, command),
+        _ => command
+            .error(ErrorKind::InvalidSubcommand, "Use cache prune")
+            .exit(),
+    }
+}
+
+fn handle_cache_prune_subcommand(arg_matches: &ArgMatches, command: &mut Command) {
+    let config = read_config(arg_matches, command);
+    let Some(cache_dir) = config.cache_dir() else {
+        command
+            .error(
+                ErrorKind::InvalidValue,
+                "The cache is disabled, cache_dir is null and the XDG cache directory is unknown",
+            )
+            .exit();
+    };
+    let max_size = if arg_matches.get_flag("all") {
+        0
+    } else if let Some(max_size_mb) = arg_matches.get_one::<u64>("max-size-mb") {
+        max_size_mb.saturating_mul(1024 * 1024)
+    } else {
+        config.cache_max_size()
+    };
+    let summary = match Cache::open(cache_dir, config.cache_max_size())
+        .and_then(|cache| cache.prune(max_size))
+    {
+        Ok(summary) => summary,
+        Err(err) => command
+            .error(
+                ErrorKind::Io,
+                format!(
+                    "Could not prune the cache at {}: {err}",
+                    cache_dir.display()
+                ),
+            )
+            .exit(),
+    };
+    println!(
+        "Removed {} entries ({} bytes) from {}, {} bytes remain",
+        summary.removed_entries,
+        summary.removed_bytes,
+        cache_dir.display(),
+        summary.remaining_bytes
+    );
 }
 
 fn handle_config_subcommand(arg_matches: &ArgMatches, command: &mut Command) {
@@ -546,7 +905,10 @@ fn handle_config_subcommand(arg_matches: &ArgMatches, command: &mut Command) {
     };
     let config_reader = BufReader::new(config_file);
     match from_reader::<BufReader<File>, Config>(config_reader) {
-        Ok(_) => {
+        Ok(config) => {
+            if let Err(err) = config.validate() {
+                command.error(ErrorKind::InvalidValue, err).exit()
+            }
             println!("Config is valid");
         }
         Err(err) => command
@@ -588,6 +950,18 @@ fn main() {
                     .num_args(1)
                     .action(ArgAction::Set)
                     .value_parser(value_parser!(u8)),
+                )
+                .arg(
+                    arg!(
+                        -f --force "Compress every image, even those the build manifest shows are unchanged"
+                    )
+                    .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    arg!(
+                        -w --watch "After compressing, keep watching the directory and compress images again as they change"
+                    )
+                    .action(ArgAction::SetTrue),
                 ),
         )
         .subcommand(
@@ -601,12 +975,93 @@ fn main() {
                     .required(true)
                     .num_args(1),
                 ),
+        )
+        .subcommand(
+            command!()
+                .name("cache")
+                .about("Manages the cache of compressed images shared by every checkout")
+                .subcommand_required(true)
+                .subcommand(
+                    command!()
+                        .name("prune")
+                        .about("Evicts the least recently used entries until the cache fits in its maximum size")
+                        .arg(
+                            arg!(
+                                -c --config <CONFIG> "Optional path to JSON config naming the cache_dir and cache_max_size_mb"
+                            )
+                            .required(false)
+                            .num_args(1),
+                        )
+                        .arg(
+                            arg!(
+                                --"max-size-mb" <MEGABYTES> "Size to prune the cache down to instead of cache_max_size_mb"
+                            )
+                            .required(false)
+                            .num_args(1)
+                            .value_parser(value_parser!(u64)),
+                        )
+                        .arg(
+                            arg!(
+                                --all "Removes every entry"
+                            )
+                            .action(ArgAction::SetTrue),
+                        ),
+                ),
+        )
+        .subcommand(
+            command!()
+                .name("transcode")
+                .about("Transcodes Basis Universal KTX2 textures to a fixed GPU format")
+                .arg(
+                    arg!(
+                        -d --dir <DIRECTORY> "Directory to recursively search for .ktx and .ktx2 textures"
+                    )
+                    .required(true)
+                    .num_args(1),
+                )
+                .arg(
+                    arg!(-f --format <FORMAT>)
+                        .help(format!(
+                            "GPU format to transcode to, one of {}",
+                            TranscodeTarget::iter()
+                                .map(|target| target.to_string())
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        ))
+                        .required(true)
+                        .num_args(1)
+                        .value_parser(TranscodeTarget::from_str),
+                )
+                .arg(
+                    arg!(
+                        -o --output <OUTPUT> "Optional directory to write the transcoded textures to, they replace the originals otherwise"
+                    )
+                    .required(false)
+                    .num_args(1),
+                )
+                .arg(
+                    arg!(
+                        -t --threads "The number of threads this process will use"
+                    )
+                    .required(false)
+                    .num_args(1)
+                    .action(ArgAction::Set)
+                    .value_parser(value_parser!(u8)),
+                )
+                .arg(
+                    arg!(
+                        -s --"skip-errors" "Keep transcoding the other textures when one fails"
+                    )
+                    .action(ArgAction::SetTrue),
+                ),
         );
     let matches = command.clone().get_matches();
 
     match matches.subcommand() {
         Some(("compress", sub_matches)) => handle_compress_subcommand(sub_matches, &mut command),
         Some(("config", sub_matches)) => handle_config_subcommand(sub_matches, &mut command),
+        Some(("cache", sub_matches)) => handle_cache_subcommand(sub_matches, &mut command),
+        Some(("transcode", sub_matches)) => handle_transcode_subcommand(sub_matches, &mut command),
         Some((unknown_command, _)) => command
             .error(
                 ErrorKind::InvalidSubcommand,
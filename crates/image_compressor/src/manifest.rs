@@ -0,0 +1,273 @@
+//! The build manifest kept next to the compressed images. It records what every output was built
+//! from, so images whose source and effective config are unchanged can be skipped on the next run
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::{read, rename, write, File},
+    io::{copy, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use ktx2_wrapper::config::CompressionConfig;
+use serde::{Deserialize, Serialize};
+use serde_json::{from_slice, to_vec, to_vec_pretty};
+
+use crate::{CompressionTypes, LocalError};
+
+pub(crate) const MANIFEST_FILE_NAME: &str = ".image_compressor_manifest.json";
+/// Bumped whenever the layout of the manifest or the meaning of its hashes changes
+const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct ManifestEntry {
+    /// BLAKE3 hash of the source image file
+    pub source_hash: String,
+    /// BLAKE3 hash of the container and the effective `CompressionConfig` of the image
+    pub config_hash: String,
+    /// Every file written for the image, the texture first
+    pub output_paths: Vec<PathBuf>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Manifest {
+    version: u32,
+    /// Keyed by the source path relative to `from_directory`
+    entries: BTreeMap<PathBuf, ManifestEntry>,
+    /// Whether `entries` changed since the manifest was loaded or saved
+    #[serde(skip)]
+    changed: bool,
+}
+
+impl Default for Manifest {
+    fn default() -> Self {
+        Self {
+            version: MANIFEST_VERSION,
+            entries: BTreeMap::new(),
+            changed: false,
+        }
+    }
+}
+
+impl Manifest {
+    /// Reads the manifest at `path`. A missing manifest, or one written by another version, is
+    /// empty so every image is compressed again
+    pub fn load(path: &Path) -> Result<Self, LocalError> {
+        let bytes = match read(path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(format!("Could not read {}: {err}", path.display()).into()),
+        };
+        let manifest = from_slice::<Self>(&bytes)
+            .map_err(|err| format!("Could not deserialize {}: {err}", path.display()))?;
+        if manifest.version == MANIFEST_VERSION {
+            Ok(manifest)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    /// Writes to a temporary file first, so an interrupted run never leaves a truncated manifest
+    pub fn save(&mut self, path: &Path) -> Result<(), LocalError> {
+        let temporary_path = Self::temporary_path(path);
+        write(&temporary_path, to_vec_pretty(self)?)?;
+        rename(&temporary_path, path)?;
+        self.changed = false;
+        Ok(())
+    }
+
+    /// Where `save` writes the manifest at `path` before renaming it
+    pub fn temporary_path(path: &Path) -> PathBuf {
+        path.with_extension("json.tmp")
+    }
+
+    pub fn is_changed(&self) -> bool {
+        self.changed
+    }
+
+    /// Whether the outputs of `source_path` were built from the same source and config, and still
+    /// exist
+    pub fn is_up_to_date(&self, source_path: &Path, source_hash: &str, config_hash: &str) -> bool {
+        self.entries.get(source_path).is_some_and(|entry| {
+            entry.source_hash == source_hash
+                && entry.config_hash == config_hash
+                && !entry.output_paths.is_empty()
+                && entry
+                    .output_paths
+                    .iter()
+                    .all(|output_path| output_path.is_file())
+        })
+    }
+
+    pub fn insert(&mut self, source_path: PathBuf, entry: ManifestEntry) {
+        if self.entries.get(&source_path) != Some(&entry) {
+            self.entries.insert(source_path, entry);
+            self.changed = true;
+        }
+    }
+
+    /// Removes and returns the entry of `source_path`, or the entries of every source under it
+    /// when it is a directory
+    pub fn remove_under(&mut self, source_path: &Path) -> Vec<ManifestEntry> {
+        let removed_source_paths = self
+            .entries
+            .keys()
+            .filter(|entry_source_path| entry_source_path.starts_with(source_path))
+            .cloned()
+            .collect::<Vec<_>>();
+        let removed_entries = removed_source_paths
+            .iter()
+            .filter_map(|removed_source_path| self.entries.remove(removed_source_path))
+            .collect::<Vec<_>>();
+        self.changed |= !removed_entries.is_empty();
+        removed_entries
+    }
+
+    /// Drops the entries of sources that no longer exist
+    pub fn retain_sources(&mut self, source_paths: &HashSet<&Path>) {
+        let entry_count = self.entries.len();
+        self.entries
+            .retain(|source_path, _| source_paths.contains(source_path.as_path()));
+        self.changed |= self.entries.len() != entry_count;
+    }
+}
+
+pub(crate) fn hash_file(path: &Path) -> Result<String, LocalError> {
+    let mut hasher = blake3::Hasher::new();
+    copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Hashes everything that decides the bytes of an output besides the source itself. The crate
+/// version is included so upgrading `image_compressor` rebuilds every image
+pub(crate) fn hash_config(
+    compression_container: CompressionTypes,
+    compression_config: &CompressionConfig,
+) -> Result<String, LocalError> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+    hasher.update(compression_container.to_string().as_bytes());
+    hasher.update(&to_vec(compression_config)?);
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::HashSet,
+        fs::{remove_file, write},
+        path::PathBuf,
+    };
+
+    use super::{Manifest, ManifestEntry};
+
+    fn entry(source_hash: &str, output_paths: Vec<PathBuf>) -> ManifestEntry {
+        ManifestEntry {
+            source_hash: source_hash.to_string(),
+            config_hash: "config".to_string(),
+            output_paths,
+        }
+    }
+
+    #[test]
+    fn is_up_to_date_needs_same_hashes_and_existing_outputs() {
+        let directory = tempfile::tempdir().unwrap();
+        let output_path = directory.path().join("image.ktx");
+        write(&output_path, b"texture").unwrap();
+        let mut manifest = Manifest::default();
+        manifest.insert(
+            "image.png".into(),
+            entry("source", vec![output_path.clone()]),
+        );
+
+        assert!(manifest.is_up_to_date("image.png".as_ref(), "source", "config"));
+        assert!(!manifest.is_up_to_date("image.png".as_ref(), "edited", "config"));
+        assert!(!manifest.is_up_to_date("image.png".as_ref(), "source", "other"));
+        assert!(!manifest.is_up_to_date("other.png".as_ref(), "source", "config"));
+
+        remove_file(&output_path).unwrap();
+        assert!(!manifest.is_up_to_date("image.png".as_ref(), "source", "config"));
+    }
+
+    #[test]
+    fn remove_under_takes_a_file_or_a_whole_directory() {
+        let mut manifest = Manifest::default();
+        for source_path in [
+            "ui/logo.png",
+            "ui/icons/close.png",
+            "ui_old/logo.png",
+            "a.png",
+        ] {
+            manifest.insert(source_path.into(), entry(source_path, vec![]));
+        }
+
+        let removed = manifest.remove_under("a.png".as_ref());
+        assert_eq!(removed, vec![entry("a.png", vec![])]);
+
+        // Matched by path components, so ui_old is kept
+        let mut removed = manifest
+            .remove_under("ui".as_ref())
+            .into_iter()
+            .map(|entry| entry.source_hash)
+            .collect::<Vec<_>>();
+        removed.sort();
+        assert_eq!(removed, ["ui/icons/close.png", "ui/logo.png"]);
+        assert_eq!(
+            manifest.entries.keys().collect::<Vec<_>>(),
+            [&PathBuf::from("ui_old/logo.png")]
+        );
+    }
+
+    #[test]
+    fn retain_sources_drops_deleted_images() {
+        let mut manifest = Manifest::default();
+        manifest.insert("kept.png".into(), entry("kept", vec![]));
+        manifest.insert("deleted.png".into(), entry("deleted", vec![]));
+
+        manifest.retain_sources(&HashSet::from(["kept.png".as_ref()]));
+        assert_eq!(
+            manifest.entries.keys().collect::<Vec<_>>(),
+            [&PathBuf::from("kept.png")]
+        );
+    }
+
+    #[test]
+    fn only_actual_changes_need_saving() {
+        let directory = tempfile::tempdir().unwrap();
+        let manifest_path = directory.path().join("manifest.json");
+        let mut manifest = Manifest::default();
+        assert!(!manifest.is_changed());
+
+        manifest.insert("image.png".into(), entry("source", vec![]));
+        assert!(manifest.is_changed());
+        manifest.save(&manifest_path).unwrap();
+        assert!(!manifest.is_changed());
+
+        manifest.insert("image.png".into(), entry("source", vec![]));
+        assert!(manifest.remove_under("other.png".as_ref()).is_empty());
+        manifest.retain_sources(&HashSet::from(["image.png".as_ref()]));
+        assert!(!manifest.is_changed());
+
+        manifest.retain_sources(&HashSet::new());
+        assert!(manifest.is_changed());
+        let mut manifest = Manifest::load(&manifest_path).unwrap();
+        assert!(!manifest.is_changed());
+        manifest.remove_under("image.png".as_ref());
+        assert!(manifest.is_changed());
+    }
+
+    #[test]
+    fn load_ignores_missing_and_outdated_manifests() {
+        let directory = tempfile::tempdir().unwrap();
+        let manifest_path = directory.path().join("manifest.json");
+        assert!(Manifest::load(&manifest_path).unwrap().entries.is_empty());
+
+        let mut manifest = Manifest::default();
+        manifest.insert("image.png".into(), entry("source", vec![]));
+        manifest.save(&manifest_path).unwrap();
+        assert_eq!(Manifest::load(&manifest_path).unwrap().entries.len(), 1);
+
+        manifest.version += 1;
+        manifest.save(&manifest_path).unwrap();
+        assert!(Manifest::load(&manifest_path).unwrap().entries.is_empty());
+    }
+}
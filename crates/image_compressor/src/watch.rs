@@ -0,0 +1,206 @@
+//! `compress --watch`. After the initial pass, `from_directory` is watched and images are
+//! compressed again as they change. Events are collected until no new one arrives for
+//! `DEBOUNCE`, so an editor saving the same file several times in a row triggers a single encode
+
+use std::{
+    collections::HashSet,
+    fs::remove_file,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, RecvTimeoutError},
+    time::Duration,
+};
+
+use log::{trace, warn};
+use notify::{RecursiveMode, Watcher};
+use shared_types::SupportedImages;
+
+use crate::{
+    config::Config, convert_image_if_changed, find_images, ignore_rules::IgnoreRules,
+    manifest::Manifest, BuildContext, LocalError,
+};
+
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Watches `from_directory` until the process is stopped or the watcher fails
+pub(crate) fn watch(
+    config: &Config,
+    ignore_rules: &IgnoreRules,
+    build_context: &BuildContext,
+) -> Result<(), LocalError> {
+    let (sender, receiver) = channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    watcher.watch(&config.from_directory, RecursiveMode::Recursive)?;
+    println!(
+        "Watching {} for changes, press Ctrl+C to stop",
+        config.from_directory.display()
+    );
+
+    let manifest_path = config.manifest_path();
+    let to_directory = config.to_directory().as_deref();
+    let mut changed_paths = HashSet::new();
+    loop {
+        let event = if changed_paths.is_empty() {
+            receiver
+                .recv()
+                .map_err(|err| format!("The file watcher stopped: {err}"))?
+        } else {
+            match receiver.recv_timeout(DEBOUNCE) {
+                Ok(event) => event,
+                Err(RecvTimeoutError::Timeout) => {
+                    handle_changes(config, ignore_rules, build_context, changed_paths.drain());
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err("The file watcher stopped".into())
+                }
+            }
+        };
+        match event {
+            Ok(event) => changed_paths.extend(event.paths.into_iter().filter(|path| {
+                !is_build_output(path, &config.from_directory, to_directory, &manifest_path)
+            })),
+            Err(err) => warn!("Error watching {}: {err}", config.from_directory.display()),
+        }
+    }
+}
+
+/// Whether `path` was written by the build itself, the manifest or an output in a `to_directory`
+/// nested in `from_directory`. Reacting to these would save the manifest again and again
+fn is_build_output(
+    path: &Path,
+    from_directory: &Path,
+    to_directory: Option<&Path>,
+    manifest_path: &Path,
+) -> bool {
+    path == manifest_path
+        || path == Manifest::temporary_path(manifest_path)
+        || to_directory.is_some_and(|to_directory| {
+            to_directory != from_directory
+                && to_directory.starts_with(from_directory)
+                && path.starts_with(to_directory)
+        })
+}
+
+/// Looks at what each changed path is once the changes settled, rather than at the events, since
+/// editors often save by writing a temporary file and renaming it over the image
+fn handle_changes(
+    config: &Config,
+    ignore_rules: &IgnoreRules,
+    build_context: &BuildContext,
+    changed_paths: impl Iterator<Item = PathBuf>,
+) {
+    for changed_path in changed_paths {
+        if ignore_rules.is_ignored(&changed_path, changed_path.is_dir()) {
+            continue;
+        }
+        if changed_path.is_dir() {
+            match find_images(&changed_path, ignore_rules) {
+                Ok(image_paths) => image_paths
+                    .iter()
+                    .for_each(|image_path| compress_image(config, build_context, image_path)),
+                Err(err) => eprintln!("Failed to scan {}: {err}", changed_path.display()),
+            }
+        } else if changed_path.is_file() {
+            if SupportedImages::is_valid_path(&changed_path) {
+                compress_image(config, build_context, &changed_path);
+            }
+        } else {
+            remove_outputs(config, build_context, &changed_path);
+        }
+    }
+
+    let mut manifest = build_context.manifest.lock().unwrap();
+    if !manifest.is_changed() {
+        return;
+    }
+    let manifest_path = config.manifest_path();
+    if let Err(err) = manifest.save(&manifest_path) {
+        eprintln!(
+            "Failed to save the build manifest {}: {err}",
+            manifest_path.display()
+        );
+    }
+}
+
+fn compress_image(config: &Config, build_context: &BuildContext, image_path: &Path) {
+    let convert = config.compression_container_for(image_path).converter();
+    match convert_image_if_changed(image_path, config, build_context, convert) {
+        Ok(true) => println!("Compressed {}", image_path.display()),
+        Ok(false) => trace!("{} is unchanged", image_path.display()),
+        Err(err) => eprintln!("Failed to convert {}: {err}", image_path.display()),
+    }
+}
+
+/// Removes the outputs the manifest recorded for a deleted image, or for every image of a deleted
+/// directory
+fn remove_outputs(config: &Config, build_context: &BuildContext, removed_path: &Path) {
+    let Ok(source_path) = removed_path.strip_prefix(&config.from_directory) else {
+        return;
+    };
+    let entries = build_context
+        .manifest
+        .lock()
+        .unwrap()
+        .remove_under(source_path);
+    for output_path in entries.iter().flat_map(|entry| &entry.output_paths) {
+        match remove_file(output_path) {
+            Ok(()) => println!("Removed {}", output_path.display()),
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => eprintln!("Failed to remove {}: {err}", output_path.display()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::is_build_output;
+
+    #[test]
+    fn the_manifest_and_nested_outputs_are_build_outputs() {
+        let from_directory = Path::new("/assets");
+        let manifest_path = Path::new("/assets/.image_compressor_manifest.json");
+        for path in [
+            manifest_path,
+            Path::new("/assets/.image_compressor_manifest.json.tmp"),
+        ] {
+            assert!(is_build_output(path, from_directory, None, manifest_path));
+        }
+        assert!(!is_build_output(
+            Path::new("/assets/albedo.png"),
+            from_directory,
+            None,
+            manifest_path
+        ));
+
+        let to_directory = Some(Path::new("/assets/built"));
+        assert!(is_build_output(
+            Path::new("/assets/built/albedo.ktx2"),
+            from_directory,
+            to_directory,
+            manifest_path
+        ));
+        assert!(!is_build_output(
+            Path::new("/assets/builtin/albedo.png"),
+            from_directory,
+            to_directory,
+            manifest_path
+        ));
+    }
+
+    #[test]
+    fn sources_under_an_enclosing_to_directory_are_not_build_outputs() {
+        let image_path = Path::new("/game/assets/albedo.png");
+        let manifest_path = Path::new("/game/.image_compressor_manifest.json");
+        for to_directory in ["/game", "/game/assets"] {
+            assert!(!is_build_output(
+                image_path,
+                Path::new("/game/assets"),
+                Some(Path::new(to_directory)),
+                manifest_path
+            ));
+        }
+    }
+}
@@ -1,82 +1,169 @@
-use std::{
-    fs::{copy, create_dir},
-    path::PathBuf,
-};
-
-use autocxx_build::Builder;
 use cfg_if::cfg_if;
-use cmake::Config;
 use miette::Result;
 
-fn main() -> Result<()> {
-    let ktx_path = PathBuf::from("../../extern/ktx").canonicalize().unwrap();
-    let out_directory = ktx_path.join("build");
-    if !out_directory.exists() {
-        create_dir(out_directory.as_path()).unwrap();
-    }
-    let mut ktx_builder = Config::new(&ktx_path);
-
-    // Need this lint because for the linux builds this variable must be mut
-    #[cfg_attr(not(target_os = "linux"), allow(unused_mut))]
-    let mut ktx_builder = ktx_builder
-        .define("CMAKE_BUILD_PARALLEL_LEVEL", "12")
-        .define("KTX_FEATURE_STATIC_LIBRARY", "ON")
-        .define("KTX_FEATURE_TESTS", "OFF")
-        .define("KTX_FEATURE_TOOLS", "OFF")
-        .out_dir(out_directory);
-
-    cfg_if! {
-        if #[cfg(target_os = "linux")] {
-            ktx_builder.define("ASTCENC_SHAREDLIB", "ON");
+cfg_if! {
+    if #[cfg(feature = "libktx")] {
+        use std::path::PathBuf;
+
+        use autocxx_build::Builder;
+        use miette::miette;
+
+        /// Where the libktx headers and libraries were found
+        struct LibKtx {
+            include_paths: Vec<PathBuf>,
+            clang_args: Vec<&'static str>,
         }
-    }
 
-    let build_output_path = ktx_builder.build();
-    let ktx_include_path = build_output_path.join("include");
-    let ktx_lib_path = build_output_path.join("lib");
-
-    let support_headers = [
-        "vk_format.h",
-        "vkformat_enum.h",
-        "gl_format.h",
-        "formatsize.h",
-    ];
-    for support_header in support_headers {
-        let support_header_path = ktx_path.join(format!("lib/{support_header}"));
-        copy(support_header_path, ktx_include_path.join(support_header)).unwrap();
-    }
-    println!("cargo:rustc-link-search=native={}", ktx_lib_path.display());
-    println!("cargo::rustc-link-lib=static=ktx");
-    cfg_if! {
-        if #[cfg(target_os = "linux")] {
-            println!("cargo::rustc-link-lib=static=astcenc-avx2-static");
+        fn main() -> Result<()> {
+            let libktx = find_libktx()?;
+
+            let local_src_path = PathBuf::from("src");
+            let mut include_paths = libktx.include_paths;
+            include_paths.push(local_src_path.clone());
+            let mut builder = Builder::new("src/lib.rs", &include_paths)
+                .extra_clang_args(&libktx.clang_args)
+                .build()?;
+
+            for clang_arg in &libktx.clang_args {
+                builder.flag(*clang_arg);
+            }
+            builder
+                .flag_if_supported("-std=c++20")
+                .file(local_src_path.join("ktx_wrappers.cpp"))
+                .compile("ktx2_wrapper");
+
+            let files_to_rerun_on = [
+                "src/config.rs",
+                "src/lib.rs",
+                "src/ktx_formats.h",
+                "src/ktx_wrappers.h",
+                "src/ktx_wrappers.cpp",
+                "src/ktx_types.rs",
+                "src/ktx_texture.rs",
+                "src/mipmaps.rs",
+                "src/alpha.rs",
+                "src/color_space.rs",
+                "src/dfd.rs",
+                "src/error.rs",
+                "src/metadata.rs",
+                "src/source_image.rs",
+                "src/verify.rs",
+                "src/texture_shape.rs",
+                "src/writer.rs",
+            ];
+
+            for file_to_rerun_on in files_to_rerun_on {
+                println!("cargo:rerun-if-changed={file_to_rerun_on}");
+            }
+
+            Ok(())
         }
-    }
 
-    let local_src_path = PathBuf::from("src");
-    let mut builder = Builder::new(
-        "src/lib.rs",
-        [&ktx_include_path, &ktx_lib_path, &local_src_path],
-    )
-    .build()?;
-
-    builder
-        .flag_if_supported("-std=c++20")
-        .file(local_src_path.join("ktx_wrappers.cpp"))
-        .compile("ktx2_wrapper");
-
-    let files_to_rerun_on = [
-        "src/config.rs",
-        "src/lib.rs",
-        "src/ktx_wrappers.h",
-        "src/ktx_wrappers.cpp",
-        "src/ktx_types.rs",
-        "src/ktx_texture.rs",
-    ];
-
-    for file_to_rerun_on in files_to_rerun_on {
-        println!("cargo:rerun-if-changed={file_to_rerun_on}");
-    }
+        cfg_if! {
+            if #[cfg(feature = "system-libktx")] {
+                /// Links an installed libktx, found in `KTX_DIR` if it is set and through pkg-config
+                /// otherwise. Only the wrapper shim is compiled
+                fn find_libktx() -> Result<LibKtx> {
+                    println!("cargo:rerun-if-env-changed=KTX_DIR");
+                    let mut include_paths = match std::env::var_os("KTX_DIR") {
+                        Some(ktx_dir) => {
+                            let ktx_dir = PathBuf::from(ktx_dir);
+                            for lib_directory in ["lib", "lib64"] {
+                                let lib_path = ktx_dir.join(lib_directory);
+                                if lib_path.exists() {
+                                    println!("cargo:rustc-link-search=native={}", lib_path.display());
+                                }
+                            }
+                            println!("cargo::rustc-link-lib=ktx");
+                            vec![ktx_dir.join("include")]
+                        }
+                        None => {
+                            let library = ["libktx", "ktx"]
+                                .into_iter()
+                                .find_map(|name| pkg_config::Config::new().probe(name).ok())
+                                .ok_or_else(|| {
+                                    miette!(
+                                        "system-libktx is enabled but libktx was not found through pkg-config, set KTX_DIR to its install prefix"
+                                    )
+                                })?;
+                            library.include_paths
+                        }
+                    };
+                    // The Vulkan headers provide VkFormat, see ktx_formats.h
+                    if let Ok(vulkan) = pkg_config::Config::new().cargo_metadata(false).probe("vulkan") {
+                        include_paths.extend(vulkan.include_paths);
+                    }
+                    Ok(LibKtx {
+                        include_paths,
+                        clang_args: vec!["-DKTX2_WRAPPER_SYSTEM_LIBKTX"],
+                    })
+                }
+            } else {
+                /// Builds the `extern/ktx` submodule with CMake and links it statically
+                fn find_libktx() -> Result<LibKtx> {
+                    use std::fs::{copy, create_dir};
+
+                    let ktx_path = PathBuf::from("../../extern/ktx");
+                    if !ktx_path.join("CMakeLists.txt").exists() {
+                        return Err(miette!(
+                            "extern/ktx is empty, run `git submodule update --init --recursive` or enable the system-libktx feature"
+                        ));
+                    }
+                    let ktx_path = ktx_path.canonicalize().unwrap();
+                    let out_directory = ktx_path.join("build");
+                    if !out_directory.exists() {
+                        create_dir(out_directory.as_path()).unwrap();
+                    }
+                    let mut ktx_builder = cmake::Config::new(&ktx_path);
+
+                    // The cmake crate already builds with as many jobs as cargo gives it through NUM_JOBS
+                    // Need this lint because for the linux builds this variable must be mut
+                    #[cfg_attr(not(target_os = "linux"), allow(unused_mut))]
+                    let mut ktx_builder = ktx_builder
+                        .define("KTX_FEATURE_STATIC_LIBRARY", "ON")
+                        .define("KTX_FEATURE_TESTS", "OFF")
+                        .define("KTX_FEATURE_TOOLS", "OFF")
+                        .out_dir(out_directory);
+
+                    cfg_if! {
+                        if #[cfg(target_os = "linux")] {
+                            ktx_builder.define("ASTCENC_SHAREDLIB", "ON");
+                        }
+                    }
 
-    Ok(())
+                    let build_output_path = ktx_builder.build();
+                    let ktx_include_path = build_output_path.join("include");
+                    let ktx_lib_path = build_output_path.join("lib");
+
+                    let support_headers = [
+                        "vk_format.h",
+                        "vkformat_enum.h",
+                        "gl_format.h",
+                        "formatsize.h",
+                    ];
+                    for support_header in support_headers {
+                        let support_header_path = ktx_path.join(format!("lib/{support_header}"));
+                        copy(support_header_path, ktx_include_path.join(support_header)).unwrap();
+                    }
+                    println!("cargo:rustc-link-search=native={}", ktx_lib_path.display());
+                    println!("cargo::rustc-link-lib=static=ktx");
+                    cfg_if! {
+                        if #[cfg(target_os = "linux")] {
+                            println!("cargo::rustc-link-lib=static=astcenc-avx2-static");
+                        }
+                    }
+
+                    Ok(LibKtx {
+                        include_paths: vec![ktx_include_path, ktx_lib_path],
+                        clang_args: Vec::new(),
+                    })
+                }
+            }
+        }
+    } else {
+        /// Without libktx there is no C++ to build, the crate is only the pure Rust writer
+        fn main() -> Result<()> {
+            Ok(())
+        }
+    }
 }
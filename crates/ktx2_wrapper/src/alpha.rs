@@ -0,0 +1,84 @@
+//! Alpha processing shared by the KTX2 and DXT paths, so both produce identical pixels. Color
+//! channels are premultiplied in linear space, sRGB encoded channels are decoded first and encoded
+//! again afterwards, and every result is rounded to the nearest representable value
+
+use std::sync::LazyLock;
+
+use half::f16;
+
+use crate::color_space::{linear_to_srgb, srgb_to_linear, ColorSpace};
+
+static SRGB_TO_LINEAR: LazyLock<[f32; 256]> =
+    LazyLock::new(|| std::array::from_fn(|value| srgb_to_linear(value as f32 / 255.)));
+
+/// Premultiplies interleaved 8 bit RGBA pixels, `color_space` tells how the color channels are
+/// encoded
+pub fn premultiply_rgba8(pixels: &mut [u8], color_space: ColorSpace) {
+    for pixel in pixels.chunks_exact_mut(4) {
+        let alpha = pixel[3];
+        for sample in &mut pixel[..3] {
+            *sample = premultiply_u8(*sample, alpha, color_space);
+        }
+    }
+}
+
+/// Premultiplies interleaved little endian 16 bit RGBA pixels, which are always linear
+pub fn premultiply_rgba16(pixels: &mut [u8]) {
+    for pixel in pixels.chunks_exact_mut(8) {
+        let alpha = u16::from_le_bytes([pixel[6], pixel[7]]) as u32;
+        for sample in pixel[..6].chunks_exact_mut(2) {
+            let value = u16::from_le_bytes([sample[0], sample[1]]) as u32;
+            let premultiplied = ((value * alpha + 32767) / 65535) as u16;
+            sample.copy_from_slice(&premultiplied.to_le_bytes());
+        }
+    }
+}
+
+/// Premultiplies interleaved little endian half float RGBA pixels, which are always linear
+pub fn premultiply_rgba16_float(pixels: &mut [u8]) {
+    for pixel in pixels.chunks_exact_mut(8) {
+        let alpha = f16::from_le_bytes([pixel[6], pixel[7]]).to_f32();
+        for sample in pixel[..6].chunks_exact_mut(2) {
+            let value = f16::from_le_bytes([sample[0], sample[1]]).to_f32();
+            sample.copy_from_slice(&f16::from_f32(value * alpha).to_le_bytes());
+        }
+    }
+}
+
+fn premultiply_u8(value: u8, alpha: u8, color_space: ColorSpace) -> u8 {
+    match (alpha, color_space) {
+        (u8::MAX, _) => value,
+        (0, _) => 0,
+        (_, ColorSpace::Linear) => ((value as u32 * alpha as u32 + 127) / 255) as u8,
+        (_, ColorSpace::Srgb) => {
+            let linear = SRGB_TO_LINEAR[value as usize] * (alpha as f32 / 255.);
+            (linear_to_srgb(linear) * 255.).round() as u8
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{premultiply_rgba16, premultiply_rgba8};
+    use crate::color_space::ColorSpace;
+
+    #[test]
+    fn premultiplies_with_rounding() {
+        let mut linear = [255, 128, 1, 128, 10, 20, 30, 255, 10, 20, 30, 0];
+        premultiply_rgba8(&mut linear, ColorSpace::Linear);
+        assert_eq!(linear, [128, 64, 1, 128, 10, 20, 30, 255, 0, 0, 0, 0]);
+
+        let mut rgba16 = [0xFF, 0xFF, 0, 0, 0, 0, 0x00, 0x80];
+        premultiply_rgba16(&mut rgba16);
+        assert_eq!(u16::from_le_bytes([rgba16[0], rgba16[1]]), 0x8000);
+    }
+
+    #[test]
+    fn srgb_premultiplies_in_linear_space() {
+        // Half coverage of white is 0.5 linear, which is 188 once sRGB encoded instead of the 128
+        // multiplying the encoded value would give
+        let mut srgb = [255, 255, 255, 128];
+        premultiply_rgba8(&mut srgb, ColorSpace::Srgb);
+        assert_eq!(srgb, [188, 188, 188, 128]);
+    }
+}
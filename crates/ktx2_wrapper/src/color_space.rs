@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+use strum::Display;
+
+#[cfg(feature = "libktx")]
+use crate::ffi::VkFormat;
+
+/// Transfer function values of the KTX2 data format descriptor
+pub const KHR_DF_TRANSFER_LINEAR: u32 = 1;
+pub const KHR_DF_TRANSFER_SRGB: u32 = 2;
+
+#[derive(Clone, Copy, Debug, Default, Display, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ColorSpace {
+    Srgb,
+    #[default]
+    Linear,
+}
+
+impl ColorSpace {
+    #[cfg(feature = "libktx")]
+    pub fn rgba8_format(&self) -> VkFormat {
+        match self {
+            Self::Srgb => VkFormat::VK_FORMAT_R8G8B8A8_SRGB,
+            Self::Linear => VkFormat::VK_FORMAT_R8G8B8A8_UNORM,
+        }
+    }
+
+    pub fn transfer_function(&self) -> u32 {
+        match self {
+            Self::Srgb => KHR_DF_TRANSFER_SRGB,
+            Self::Linear => KHR_DF_TRANSFER_LINEAR,
+        }
+    }
+
+    pub fn from_transfer_function(transfer_function: u32) -> Option<Self> {
+        match transfer_function {
+            KHR_DF_TRANSFER_SRGB => Some(Self::Srgb),
+            KHR_DF_TRANSFER_LINEAR => Some(Self::Linear),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub(crate) fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1. / 2.4) - 0.055
+    }
+}
@@ -1,26 +1,49 @@
+use std::ffi::c_char;
+
 use autocxx::WithinUniquePtr;
 use cxx::UniquePtr;
 use lazy_regex::{lazy_regex, Lazy, Regex};
 use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+use shared_types::LocalError;
 use strum::{Display, EnumDiscriminants};
 
 use crate::{
+    color_space::ColorSpace,
     ffi::{
         ktxAstcParams, ktx_pack_astc_block_dimension_e, ktx_pack_astc_encoder_mode_e,
         ktx_pack_astc_quality_levels_e, KtxBasisETC1SParams, KtxBasisParams, KtxBasisUASTCParams,
     },
-    ffi2::KtxPackUastcFlags,
-    ktx_texture::{ZLibDeflationValue, ZstdDeflationValue},
+    ffi2::{KtxPackUastcFlags, OptionalF32},
+    metadata::MetadataConfig,
+    mipmaps::MipmapConfig,
+    source_image::{Channels, PixelFormat},
+    verify::VerifyConfig,
 };
 
-static INPUT_SWIZZLE_REGEX: Lazy<Regex> = lazy_regex!("/^[rgba01]{4}$/");
+pub use crate::deflation::{ZLib, Zstd};
+
+static INPUT_SWIZZLE_REGEX: Lazy<Regex> = lazy_regex!("^[rgba01]{4}$");
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct CompressionConfig {
     pub config_type: KTXCompressionConfigTypes,
     pub config: KTXCompressionConfig,
     #[serde(default = "default_premultiply")]
     pub premultiply: Option<bool>,
+    #[serde(default)]
+    pub color_space: ColorSpace,
+    #[serde(default)]
+    pub mipmaps: Option<MipmapConfig>,
+    #[serde(default)]
+    pub supercompression: Option<Supercompression>,
+    #[serde(default)]
+    pub metadata: Option<MetadataConfig>,
+    /// Channels 8 bit sources are stored with, `Auto` keeps the channels of the source
+    #[serde(default)]
+    pub channels: Channels,
+    /// Decodes the encoded texture and fails if it is too far from the source
+    #[serde(default)]
+    pub verify: Option<VerifyConfig>,
 }
 
 fn default_premultiply() -> Option<bool> {
@@ -34,9 +57,86 @@ impl CompressionConfig {
     pub fn config_type(&self) -> &KTXCompressionConfigTypes {
         &self.config_type
     }
+    pub fn supercompression(&self) -> Option<&Supercompression> {
+        self.supercompression.as_ref()
+    }
+
+    /// Checks that `config` matches `config_type` and that the encode and supercompression steps
+    /// can be chained
+    pub fn validate(&self) -> Result<(), LocalError> {
+        let config_discriminant = KTXCompressionConfigTypes::from(&self.config);
+        if config_discriminant != self.config_type {
+            return Err(format!(
+                "config_type {} does not match the {config_discriminant} config",
+                self.config_type
+            )
+            .into());
+        }
+        if let KTXCompressionConfig::ASTC(astc) = &self.config {
+            astc.validate()?;
+            if self.verify.is_some() {
+                return Err("ASTC textures cannot be decoded by libktx, remove verify".into());
+            }
+        }
+        let Some(supercompression) = &self.supercompression else {
+            return Ok(());
+        };
+        match &self.config {
+            KTXCompressionConfig::BasisUniversalBasisLZETC1s(_) => Err(format!(
+                "{supercompression} supercompression cannot follow BasisUniversalBasisLZETC1s, which is already BasisLZ supercompressed"
+            )
+            .into()),
+            KTXCompressionConfig::ZLib(_) | KTXCompressionConfig::Zstd(_) => Err(format!(
+                "{supercompression} supercompression cannot follow {config_discriminant}, a texture can only be supercompressed once"
+            )
+            .into()),
+            KTXCompressionConfig::BasisUniversalUASTC(_) | KTXCompressionConfig::ASTC(_) => Ok(()),
+        }
+    }
+
+    /// Like `validate`, but also checks that the encode step accepts images in `pixel_format`.
+    /// Basis Universal only takes 8 bit input, 16 bit images can only be deflated and float images
+    /// can additionally be encoded as HDR ASTC
+    pub fn validate_for(&self, pixel_format: PixelFormat) -> Result<(), LocalError> {
+        self.validate()?;
+        match (&self.config, pixel_format) {
+            (KTXCompressionConfig::ASTC(astc), pixel_format)
+                if pixel_format.is_8_bit() && astc.is_hdr() =>
+            {
+                Err("ASTC HDR mode needs floating point images, use the LDR or default mode".into())
+            }
+            (_, pixel_format) if pixel_format.is_8_bit() => Ok(()),
+            (KTXCompressionConfig::ZLib(_) | KTXCompressionConfig::Zstd(_), _) => Ok(()),
+            (KTXCompressionConfig::ASTC(astc), PixelFormat::Rgba16Float) => {
+                if matches!(
+                    astc.mode,
+                    Some(ktx_pack_astc_encoder_mode_e::KTX_PACK_ASTC_ENCODER_MODE_LDR)
+                ) {
+                    Err(
+                        "ASTC LDR mode cannot encode floating point images, use the HDR mode"
+                            .into(),
+                    )
+                } else {
+                    Ok(())
+                }
+            }
+            (config, pixel_format) => Err(format!(
+                "{} cannot encode {pixel_format:?} images",
+                KTXCompressionConfigTypes::from(config)
+            )
+            .into()),
+        }
+    }
 }
 
-#[derive(Debug, EnumDiscriminants, Serialize, Deserialize)]
+/// Supercompression applied after the encode step of a `CompressionConfig`
+#[derive(Clone, Debug, Display, Serialize, Deserialize)]
+pub enum Supercompression {
+    ZLib(ZLib),
+    Zstd(Zstd),
+}
+
+#[derive(Clone, Debug, EnumDiscriminants, Serialize, Deserialize)]
 #[strum_discriminants(
     derive(Serialize, Deserialize, Display),
     name(KTXCompressionConfigTypes),
@@ -63,7 +163,7 @@ impl Default for KTXCompressionConfig {
 }
 
 #[serde_with::skip_serializing_none]
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct BasisUniversalBasisLZETC1s {
     pub verbose: Option<bool>,
     pub no_sse: Option<bool>,
@@ -89,72 +189,32 @@ pub struct BasisUniversalBasisLZETC1s {
 
 impl From<&BasisUniversalBasisLZETC1s> for (KtxBasisParams, KtxBasisETC1SParams) {
     fn from(value: &BasisUniversalBasisLZETC1s) -> Self {
-        let mut ktx_basis_params = KtxBasisParams::default();
-        let mut ktx_etc1s_params = KtxBasisETC1SParams::default();
-
-        ktx_basis_params.uastc = false;
-        if let Some(verbose) = value.verbose {
-            let boxed_verbose = Box::new(verbose);
-            ktx_basis_params.verbose = Box::into_raw(boxed_verbose);
-        }
-        if let Some(no_sse) = value.no_sse {
-            let boxed_no_sse = Box::new(no_sse);
-            ktx_basis_params.no_sse = Box::into_raw(boxed_no_sse);
-        }
-        if let Some(thread_count) = value.thread_count {
-            let boxed_thread_count = Box::new(thread_count);
-            ktx_basis_params.thread_count = Box::into_raw(boxed_thread_count);
-        }
-        if let Some(compression_level) = value.compression_level {
-            let boxed_compression_level = Box::new(compression_level);
-            ktx_etc1s_params.compression_level = Box::into_raw(boxed_compression_level);
-        }
-        if let Some(quality_level) = value.quality_level {
-            let boxed_quality_level = Box::new(quality_level);
-            ktx_etc1s_params.quality_level = Box::into_raw(boxed_quality_level);
-        }
-        if let Some(max_endpoints) = value.max_endpoints {
-            let boxed_max_endpoints = Box::new(max_endpoints);
-            ktx_etc1s_params.max_endpoints = Box::into_raw(boxed_max_endpoints);
-        }
-        if let Some(endpoint_rdo_threshold) = value.endpoint_rdo_threshold {
-            let boxed_endpoint_rdo_threshold = Box::new(endpoint_rdo_threshold);
-            ktx_etc1s_params.endpoint_rdo_threshold = Box::into_raw(boxed_endpoint_rdo_threshold);
-        }
-        if let Some(max_selectors) = value.max_selectors {
-            let boxed_max_selectors = Box::new(max_selectors);
-            ktx_etc1s_params.max_selectors = Box::into_raw(boxed_max_selectors);
-        }
-        if let Some(input_swizzle) = &value.input_swizzle {
-            let boxed_input_swizzle = Box::new((*input_swizzle).clone());
-            ktx_etc1s_params.input_swizzle = Box::into_raw(boxed_input_swizzle);
-        }
-        if let Some(normal_map) = value.normal_map {
-            let boxed_normal_map = Box::new(normal_map);
-            ktx_etc1s_params.normal_map = Box::into_raw(boxed_normal_map);
-        }
-        if let Some(separate_rgt_to_rgba) = value.separate_rgt_to_rgba {
-            let boxed_separate_rgt_to_rgba = Box::new(separate_rgt_to_rgba);
-            ktx_etc1s_params.separate_rgt_to_rgba = Box::into_raw(boxed_separate_rgt_to_rgba);
-        }
-        if let Some(pre_swizzle) = value.pre_swizzle {
-            let boxed_pre_swizzle = Box::new(pre_swizzle);
-            ktx_etc1s_params.pre_swizzle = Box::into_raw(boxed_pre_swizzle);
-        }
-        if let Some(no_endpoint_rdo) = value.no_endpoint_rdo {
-            let boxed_no_endpoint_rdo = Box::new(no_endpoint_rdo);
-            ktx_etc1s_params.no_endpoint_rdo = Box::into_raw(boxed_no_endpoint_rdo);
-        }
-        if let Some(no_selector_rdo) = value.no_selector_rdo {
-            let boxed_no_selector_rdo = Box::new(no_selector_rdo);
-            ktx_etc1s_params.no_selector_rdo = Box::into_raw(boxed_no_selector_rdo);
-        }
+        let ktx_basis_params = KtxBasisParams {
+            uastc: false,
+            verbose: value.verbose.into(),
+            no_sse: value.no_sse.into(),
+            thread_count: value.thread_count.into(),
+        };
+        let ktx_etc1s_params = KtxBasisETC1SParams {
+            compression_level: value.compression_level.into(),
+            quality_level: value.quality_level.into(),
+            max_endpoints: value.max_endpoints.into(),
+            endpoint_rdo_threshold: value.endpoint_rdo_threshold.into(),
+            max_selectors: value.max_selectors.into(),
+            selector_rdo_threshold: OptionalF32::default(),
+            input_swizzle: value.input_swizzle.clone().unwrap_or_default(),
+            normal_map: value.normal_map.into(),
+            separate_rgt_to_rgba: value.separate_rgt_to_rgba.into(),
+            pre_swizzle: value.pre_swizzle.into(),
+            no_endpoint_rdo: value.no_endpoint_rdo.into(),
+            no_selector_rdo: value.no_selector_rdo.into(),
+        };
         (ktx_basis_params, ktx_etc1s_params)
     }
 }
 
 #[serde_with::skip_serializing_none]
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct BasisUniversalUASTC {
     pub verbose: Option<bool>,
     pub no_sse: Option<bool>,
@@ -190,79 +250,35 @@ pub struct BasisUniversalUASTC {
 
 impl From<&BasisUniversalUASTC> for (KtxBasisParams, KtxBasisUASTCParams) {
     fn from(value: &BasisUniversalUASTC) -> Self {
-        let mut ktx_basis_params = KtxBasisParams::default();
-        let mut ktx_uastc_params = KtxBasisUASTCParams::default();
-
-        ktx_basis_params.uastc = true;
-
-        if let Some(verbose) = value.verbose {
-            let boxed_verbose = Box::new(verbose);
-            ktx_basis_params.verbose = Box::into_raw(boxed_verbose);
-        }
-        if let Some(no_sse) = value.no_sse {
-            let boxed_no_sse = Box::new(no_sse);
-            ktx_basis_params.no_sse = Box::into_raw(boxed_no_sse);
-        }
-        if let Some(thread_count) = value.thread_count {
-            let boxed_thread_count = Box::new(thread_count);
-            ktx_basis_params.thread_count = Box::into_raw(boxed_thread_count);
-        }
-        if let Some(input_swizzle) = &value.input_swizzle {
-            let boxed_input_swizzle = Box::new((*input_swizzle).clone());
-            ktx_uastc_params.input_swizzle = Box::into_raw(boxed_input_swizzle);
-        }
-        if let Some(pre_swizzle) = value.pre_swizzle {
-            let boxed_pre_swizzle = Box::new(pre_swizzle);
-            ktx_uastc_params.pre_swizzle = Box::into_raw(boxed_pre_swizzle);
-        }
-        if let Some(uastc_flags) = value.uastc_flags {
-            let boxed_uastc_flags = Box::new(uastc_flags);
-            ktx_uastc_params.uastc_flags = Box::into_raw(boxed_uastc_flags);
-        }
-        if let Some(uastc_rdo) = value.uastc_rdo {
-            let boxed_uastc_rdo = Box::new(uastc_rdo);
-            ktx_uastc_params.uastc_rdo = Box::into_raw(boxed_uastc_rdo);
-        }
-        if let Some(uastc_rdo_quality_scalar) = value.uastc_rdo_quality_scalar {
-            let boxed_uastc_rdo_quality_scalar = Box::new(uastc_rdo_quality_scalar);
-            ktx_uastc_params.uastc_rdo_quality_scalar =
-                Box::into_raw(boxed_uastc_rdo_quality_scalar);
-        }
-        if let Some(uastc_rdo_dict_size) = value.uastc_rdo_dict_size {
-            let boxed_uastc_rdo_dict_size = Box::new(uastc_rdo_dict_size);
-            ktx_uastc_params.uastc_rdo_dict_size = Box::into_raw(boxed_uastc_rdo_dict_size);
-        }
-        if let Some(uastc_rdo_max_smooth_block_error_scale) =
-            value.uastc_rdo_max_smooth_block_error_scale
-        {
-            let boxed_uastc_rdo_max_smooth_block_error_scale =
-                Box::new(uastc_rdo_max_smooth_block_error_scale);
-            ktx_uastc_params.uastc_rdo_max_smooth_block_error_scale =
-                Box::into_raw(boxed_uastc_rdo_max_smooth_block_error_scale);
-        }
-        if let Some(uastc_rdo_max_smooth_block_std_dev) = value.uastc_rdo_max_smooth_block_std_dev {
-            let boxed_uastc_rdo_max_smooth_block_std_dev =
-                Box::new(uastc_rdo_max_smooth_block_std_dev);
-            ktx_uastc_params.uastc_rdo_max_smooth_block_std_dev =
-                Box::into_raw(boxed_uastc_rdo_max_smooth_block_std_dev);
-        }
-        if let Some(uastc_rdo_dont_favor_simpler_modes) = value.uastc_rdo_dont_favor_simpler_modes {
-            let boxed_uastc_rdo_dont_favor_simpler_modes =
-                Box::new(uastc_rdo_dont_favor_simpler_modes);
-            ktx_uastc_params.uastc_rdo_dont_favor_simpler_modes =
-                Box::into_raw(boxed_uastc_rdo_dont_favor_simpler_modes);
-        }
-        if let Some(uastc_rdo_no_multithreading) = value.uastc_rdo_no_multithreading {
-            let boxed_uastc_rdo_no_multithreading = Box::new(uastc_rdo_no_multithreading);
-            ktx_uastc_params.uastc_rdo_no_multithreading =
-                Box::into_raw(boxed_uastc_rdo_no_multithreading);
-        }
+        let ktx_basis_params = KtxBasisParams {
+            uastc: true,
+            verbose: value.verbose.into(),
+            no_sse: value.no_sse.into(),
+            thread_count: value.thread_count.into(),
+        };
+        let ktx_uastc_params = KtxBasisUASTCParams {
+            uastc_flags: value
+                .uastc_flags
+                .map(|uastc_flags| uastc_flags.value)
+                .into(),
+            uastc_rdo: value.uastc_rdo.into(),
+            uastc_rdo_quality_scalar: value.uastc_rdo_quality_scalar.into(),
+            uastc_rdo_dict_size: value.uastc_rdo_dict_size.into(),
+            uastc_rdo_max_smooth_block_error_scale: value
+                .uastc_rdo_max_smooth_block_error_scale
+                .into(),
+            uastc_rdo_max_smooth_block_std_dev: value.uastc_rdo_max_smooth_block_std_dev.into(),
+            uastc_rdo_dont_favor_simpler_modes: value.uastc_rdo_dont_favor_simpler_modes.into(),
+            uastc_rdo_no_multithreading: value.uastc_rdo_no_multithreading.into(),
+            input_swizzle: value.input_swizzle.clone().unwrap_or_default(),
+            pre_swizzle: value.pre_swizzle.into(),
+        };
         (ktx_basis_params, ktx_uastc_params)
     }
 }
 
 #[serde_with::skip_serializing_none]
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct ASTC {
     pub verbose: Option<bool>,
     #[serde(default, deserialize_with = "deserialize_thread_count")]
@@ -291,6 +307,47 @@ pub struct ASTC {
     pub input_swizzle: Option<String>,
 }
 
+impl ASTC {
+    pub fn is_hdr(&self) -> bool {
+        matches!(
+            self.mode,
+            Some(ktx_pack_astc_encoder_mode_e::KTX_PACK_ASTC_ENCODER_MODE_HDR)
+        )
+    }
+
+    /// Whether `block_dimension` is one of the 3D block footprints, which only volume textures
+    /// can use
+    pub fn has_3d_block_dimension(&self) -> bool {
+        matches!(
+            self.block_dimension,
+            Some(
+                ktx_pack_astc_block_dimension_e::KTX_PACK_ASTC_BLOCK_DIMENSION_3x3x3
+                    | ktx_pack_astc_block_dimension_e::KTX_PACK_ASTC_BLOCK_DIMENSION_4x3x3
+                    | ktx_pack_astc_block_dimension_e::KTX_PACK_ASTC_BLOCK_DIMENSION_4x4x3
+                    | ktx_pack_astc_block_dimension_e::KTX_PACK_ASTC_BLOCK_DIMENSION_4x4x4
+                    | ktx_pack_astc_block_dimension_e::KTX_PACK_ASTC_BLOCK_DIMENSION_5x4x4
+                    | ktx_pack_astc_block_dimension_e::KTX_PACK_ASTC_BLOCK_DIMENSION_5x5x4
+                    | ktx_pack_astc_block_dimension_e::KTX_PACK_ASTC_BLOCK_DIMENSION_5x5x5
+                    | ktx_pack_astc_block_dimension_e::KTX_PACK_ASTC_BLOCK_DIMENSION_6x5x5
+                    | ktx_pack_astc_block_dimension_e::KTX_PACK_ASTC_BLOCK_DIMENSION_6x6x5
+                    | ktx_pack_astc_block_dimension_e::KTX_PACK_ASTC_BLOCK_DIMENSION_6x6x6
+            )
+        )
+    }
+
+    /// Checks that the options can be combined. Normal maps are encoded as two LDR channels, so
+    /// they cannot use the HDR mode
+    pub fn validate(&self) -> Result<(), LocalError> {
+        if self.is_hdr() && self.normal_map == Some(true) {
+            return Err("ASTC normal_map cannot be combined with the HDR mode".into());
+        }
+        if self.normal_map == Some(true) && self.input_swizzle.is_some() {
+            return Err("ASTC normal_map already swizzles the input, remove input_swizzle".into());
+        }
+        Ok(())
+    }
+}
+
 impl From<&ASTC> for UniquePtr<ktxAstcParams> {
     fn from(value: &ASTC) -> Self {
         let mut ktx_astc_params = ktxAstcParams::new().within_unique_ptr();
@@ -309,15 +366,25 @@ impl From<&ASTC> for UniquePtr<ktxAstcParams> {
         if let Some(quality_level) = value.quality_level {
             ktx_astc_params.qualityLevel = quality_level as u32;
         }
+        if let Some(normal_map) = value.normal_map {
+            ktx_astc_params.normalMap = normal_map;
+        }
+        if let Some(perceptual) = value.perceptual {
+            ktx_astc_params.perceptual = perceptual;
+        }
+        if let Some(input_swizzle) = &value.input_swizzle {
+            for (swizzle_component, swizzle_byte) in ktx_astc_params
+                .inputSwizzle
+                .iter_mut()
+                .zip(input_swizzle.bytes())
+            {
+                *swizzle_component = swizzle_byte as c_char;
+            }
+        }
         ktx_astc_params
     }
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
-pub struct ZLib {
-    pub deflation_value: ZLibDeflationValue,
-}
-
 macro_rules! create_deserialize_number_range {
     ($(($function_name:ident, $number_type:ty, $low_value:literal, $high_value:literal)), *) => {
         $(
@@ -331,11 +398,6 @@ macro_rules! create_deserialize_number_range {
     };
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
-pub struct Zstd {
-    pub deflation_value: ZstdDeflationValue,
-}
-
 create_deserialize_number_range!(
     (deserialize_thread_count, u32, 1, 16),
     (deserialize_compression_level, u32, 0, 5),
@@ -444,3 +506,149 @@ fn deserialize_uastc_flags<'a, D: Deserializer<'a>>(
 
     Ok(Some(u32_value.into()))
 }
+
+#[cfg(test)]
+mod test {
+    use cxx::UniquePtr;
+
+    use super::{
+        BasisUniversalBasisLZETC1s, BasisUniversalUASTC, CompressionConfig, KTXCompressionConfig,
+        KTXCompressionConfigTypes, ASTC,
+    };
+    use crate::{
+        ffi::{
+            ktxAstcParams, ktx_pack_astc_block_dimension_e, ktx_pack_astc_encoder_mode_e,
+            ktx_pack_astc_quality_levels_e, KtxBasisETC1SParams, KtxBasisParams,
+            KtxBasisUASTCParams,
+        },
+        source_image::PixelFormat,
+    };
+
+    fn astc_config(astc: ASTC) -> CompressionConfig {
+        CompressionConfig {
+            config_type: KTXCompressionConfigTypes::ASTC,
+            config: KTXCompressionConfig::ASTC(astc),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn astc_params_receive_every_option() {
+        let astc = ASTC {
+            verbose: Some(true),
+            thread_count: Some(3),
+            block_dimension: Some(
+                ktx_pack_astc_block_dimension_e::KTX_PACK_ASTC_BLOCK_DIMENSION_6x6,
+            ),
+            mode: Some(ktx_pack_astc_encoder_mode_e::KTX_PACK_ASTC_ENCODER_MODE_LDR),
+            quality_level: Some(
+                ktx_pack_astc_quality_levels_e::KTX_PACK_ASTC_QUALITY_LEVEL_THOROUGH,
+            ),
+            normal_map: Some(true),
+            perceptual: Some(true),
+            input_swizzle: Some("bgra".to_string()),
+        };
+        let params: UniquePtr<ktxAstcParams> = (&astc).into();
+        assert!(params.verbose);
+        assert_eq!(params.threadCount, 3);
+        assert_eq!(
+            params.blockDimension,
+            ktx_pack_astc_block_dimension_e::KTX_PACK_ASTC_BLOCK_DIMENSION_6x6 as u32
+        );
+        assert_eq!(
+            params.mode,
+            ktx_pack_astc_encoder_mode_e::KTX_PACK_ASTC_ENCODER_MODE_LDR as u32
+        );
+        assert_eq!(
+            params.qualityLevel,
+            ktx_pack_astc_quality_levels_e::KTX_PACK_ASTC_QUALITY_LEVEL_THOROUGH as u32
+        );
+        assert!(params.normalMap);
+        assert!(params.perceptual);
+        assert_eq!(
+            params.inputSwizzle.map(|component| component as u8),
+            *b"bgra"
+        );
+    }
+
+    #[test]
+    fn astc_options_are_validated() {
+        let hdr_normal_map = astc_config(ASTC {
+            mode: Some(ktx_pack_astc_encoder_mode_e::KTX_PACK_ASTC_ENCODER_MODE_HDR),
+            normal_map: Some(true),
+            ..Default::default()
+        });
+        assert!(hdr_normal_map.validate().is_err());
+
+        let hdr = astc_config(ASTC {
+            mode: Some(ktx_pack_astc_encoder_mode_e::KTX_PACK_ASTC_ENCODER_MODE_HDR),
+            ..Default::default()
+        });
+        assert!(hdr.validate_for(PixelFormat::Rgba8).is_err());
+        assert!(hdr.validate_for(PixelFormat::Rgba16Float).is_ok());
+
+        let ldr = astc_config(ASTC {
+            mode: Some(ktx_pack_astc_encoder_mode_e::KTX_PACK_ASTC_ENCODER_MODE_LDR),
+            ..Default::default()
+        });
+        assert!(ldr.validate_for(PixelFormat::Rgba8).is_ok());
+        assert!(ldr.validate_for(PixelFormat::Rgba16Float).is_err());
+
+        let volume_block = ASTC {
+            block_dimension: Some(
+                ktx_pack_astc_block_dimension_e::KTX_PACK_ASTC_BLOCK_DIMENSION_4x4x4,
+            ),
+            ..Default::default()
+        };
+        assert!(volume_block.has_3d_block_dimension());
+        assert!(!ASTC::default().has_3d_block_dimension());
+    }
+
+    #[test]
+    fn basis_params_only_set_configured_options() {
+        let etc1s = BasisUniversalBasisLZETC1s {
+            thread_count: Some(4),
+            no_endpoint_rdo: Some(true),
+            input_swizzle: Some("rgb1".to_string()),
+            ..Default::default()
+        };
+        let (basis_params, etc1s_params): (KtxBasisParams, KtxBasisETC1SParams) = (&etc1s).into();
+        assert!(!basis_params.uastc);
+        assert_eq!(Option::<u32>::from(basis_params.thread_count), Some(4));
+        assert_eq!(Option::<bool>::from(basis_params.verbose), None);
+        assert_eq!(
+            Option::<bool>::from(etc1s_params.no_endpoint_rdo),
+            Some(true)
+        );
+        assert_eq!(Option::<bool>::from(etc1s_params.no_selector_rdo), None);
+        assert_eq!(etc1s_params.input_swizzle, "rgb1");
+
+        let uastc = BasisUniversalUASTC {
+            pre_swizzle: Some(false),
+            uastc_rdo_dict_size: Some(1024),
+            ..Default::default()
+        };
+        let (basis_params, uastc_params): (KtxBasisParams, KtxBasisUASTCParams) = (&uastc).into();
+        assert!(basis_params.uastc);
+        assert_eq!(Option::<bool>::from(uastc_params.pre_swizzle), Some(false));
+        assert_eq!(
+            Option::<u32>::from(uastc_params.uastc_rdo_dict_size),
+            Some(1024)
+        );
+        assert_eq!(Option::<u32>::from(uastc_params.uastc_flags), None);
+        assert!(uastc_params.input_swizzle.is_empty());
+    }
+
+    #[test]
+    fn input_swizzle_is_validated() {
+        let uastc: BasisUniversalUASTC =
+            serde_json::from_str(r#"{ "input_swizzle": "rgb1" }"#).unwrap();
+        assert_eq!(uastc.input_swizzle.as_deref(), Some("rgb1"));
+        assert!(
+            serde_json::from_str::<BasisUniversalUASTC>(r#"{ "input_swizzle": "rgbx" }"#).is_err()
+        );
+        assert!(
+            serde_json::from_str::<BasisUniversalUASTC>(r#"{ "input_swizzle": "rgb" }"#).is_err()
+        );
+    }
+}
@@ -0,0 +1,107 @@
+//! Settings of the `ZLib` and `Zstd` steps. They need no libktx types, so the Rust writer reads
+//! them from the same JSON as `CompressionConfig`
+
+use std::fmt::Display;
+
+use num_traits::Bounded;
+use serde::{Deserialize, Deserializer, Serialize};
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ZLib {
+    pub deflation_value: ZLibDeflationValue,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Zstd {
+    pub deflation_value: ZstdDeflationValue,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ZLibDeflationValue(u8);
+
+impl Display for ZLibDeflationValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl<'a> Deserialize<'a> for ZLibDeflationValue {
+    fn deserialize<D: Deserializer<'a>>(deserializer: D) -> Result<Self, D::Error> {
+        let u8_value = u8::deserialize(deserializer)?;
+
+        Ok(u8_value.into())
+    }
+}
+
+impl Default for ZLibDeflationValue {
+    fn default() -> Self {
+        Self(7)
+    }
+}
+
+impl Bounded for ZLibDeflationValue {
+    fn min_value() -> Self {
+        Self(1)
+    }
+
+    fn max_value() -> Self {
+        Self(9)
+    }
+}
+
+impl From<u8> for ZLibDeflationValue {
+    fn from(value: u8) -> Self {
+        Self(value).clamp(Self::min_value(), Self::max_value())
+    }
+}
+
+impl From<ZLibDeflationValue> for u32 {
+    fn from(value: ZLibDeflationValue) -> Self {
+        value.0 as u32
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ZstdDeflationValue(u8);
+
+impl Display for ZstdDeflationValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl<'a> Deserialize<'a> for ZstdDeflationValue {
+    fn deserialize<D: Deserializer<'a>>(deserializer: D) -> Result<Self, D::Error> {
+        let u8_value = u8::deserialize(deserializer)?;
+
+        Ok(u8_value.into())
+    }
+}
+
+impl Default for ZstdDeflationValue {
+    fn default() -> Self {
+        Self(17)
+    }
+}
+
+impl Bounded for ZstdDeflationValue {
+    fn min_value() -> Self {
+        Self(1)
+    }
+
+    fn max_value() -> Self {
+        Self(22)
+    }
+}
+
+impl From<u8> for ZstdDeflationValue {
+    fn from(value: u8) -> Self {
+        Self(value).clamp(Self::min_value(), Self::max_value())
+    }
+}
+
+impl From<ZstdDeflationValue> for u32 {
+    fn from(value: ZstdDeflationValue) -> Self {
+        value.0 as u32
+    }
+}
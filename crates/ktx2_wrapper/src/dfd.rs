@@ -0,0 +1,124 @@
+//! Accessors and a builder for the basic descriptor block of a KTX2 data format descriptor. The
+//! descriptor starts with its total byte size, followed by the basic block whose third word packs
+//! the color model, color primaries, transfer function and flags, one byte each
+
+use crate::{color_space::KHR_DF_TRANSFER_SRGB, source_image::PixelFormat};
+
+const BASIC_BLOCK_MODEL_WORD: usize = 3;
+const TRANSFER_FUNCTION_SHIFT: u32 = 16;
+const FLAGS_SHIFT: u32 = 24;
+
+/// Set in the flags byte when the color channels are premultiplied by alpha
+pub const KHR_DF_FLAG_ALPHA_PREMULTIPLIED: u32 = 1;
+
+const BASIC_BLOCK_HEADER_SIZE: u32 = 24;
+const BASIC_BLOCK_SAMPLE_SIZE: u32 = 16;
+const KHR_DF_VERSIONNUMBER_1_3: u32 = 2;
+const KHR_DF_MODEL_RGBSDA: u32 = 1;
+const KHR_DF_PRIMARIES_BT709: u32 = 1;
+const KHR_DF_CHANNEL_RGBSDA_ALPHA: u32 = 15;
+const KHR_DF_SAMPLE_DATATYPE_LINEAR: u32 = 0x10;
+const KHR_DF_SAMPLE_DATATYPE_SIGNED: u32 = 0x40;
+const KHR_DF_SAMPLE_DATATYPE_FLOAT: u32 = 0x80;
+
+pub fn transfer_function(dfd: &[u32]) -> Option<u32> {
+    dfd.get(BASIC_BLOCK_MODEL_WORD)
+        .map(|model_word| (model_word >> TRANSFER_FUNCTION_SHIFT) & 0xFF)
+}
+
+pub fn set_transfer_function(dfd: &mut [u32], transfer_function: u32) -> bool {
+    let Some(model_word) = dfd.get_mut(BASIC_BLOCK_MODEL_WORD) else {
+        return false;
+    };
+    *model_word = (*model_word & !(0xFF << TRANSFER_FUNCTION_SHIFT))
+        | ((transfer_function & 0xFF) << TRANSFER_FUNCTION_SHIFT);
+    true
+}
+
+pub fn alpha_premultiplied(dfd: &[u32]) -> Option<bool> {
+    dfd.get(BASIC_BLOCK_MODEL_WORD)
+        .map(|model_word| (model_word >> FLAGS_SHIFT) & KHR_DF_FLAG_ALPHA_PREMULTIPLIED != 0)
+}
+
+pub fn set_alpha_premultiplied(dfd: &mut [u32], premultiplied: bool) -> bool {
+    let Some(model_word) = dfd.get_mut(BASIC_BLOCK_MODEL_WORD) else {
+        return false;
+    };
+    let flag = KHR_DF_FLAG_ALPHA_PREMULTIPLIED << FLAGS_SHIFT;
+    if premultiplied {
+        *model_word |= flag;
+    } else {
+        *model_word &= !flag;
+    }
+    true
+}
+
+/// Builds the descriptor of an uncompressed `pixel_format` texture, with one sample per channel
+/// in RGBA order. Alpha is always linear, even when the color channels use the sRGB transfer
+/// function
+pub fn uncompressed_descriptor(pixel_format: PixelFormat, transfer_function: u32) -> Vec<u32> {
+    let channels = pixel_format.channels() as u32;
+    let sample_bits = pixel_format.bytes_per_sample() as u32 * 8;
+    let block_size = BASIC_BLOCK_HEADER_SIZE + BASIC_BLOCK_SAMPLE_SIZE * channels;
+
+    let mut descriptor = vec![
+        4 + block_size,
+        // Khronos vendor id and basic descriptor type are both 0
+        0,
+        KHR_DF_VERSIONNUMBER_1_3 | (block_size << 16),
+        KHR_DF_MODEL_RGBSDA
+            | (KHR_DF_PRIMARIES_BT709 << 8)
+            | ((transfer_function & 0xFF) << TRANSFER_FUNCTION_SHIFT),
+        // A texel block of a single texel
+        0,
+        pixel_format.bytes_per_pixel() as u32,
+        0,
+    ];
+    for channel in 0..channels {
+        let channel_id = if channel == 3 {
+            KHR_DF_CHANNEL_RGBSDA_ALPHA
+        } else {
+            channel
+        };
+        let mut channel_type = channel_id;
+        if channel_id == KHR_DF_CHANNEL_RGBSDA_ALPHA && transfer_function == KHR_DF_TRANSFER_SRGB {
+            channel_type |= KHR_DF_SAMPLE_DATATYPE_LINEAR;
+        }
+        let (sample_lower, sample_upper) = if pixel_format.is_hdr() {
+            channel_type |= KHR_DF_SAMPLE_DATATYPE_FLOAT | KHR_DF_SAMPLE_DATATYPE_SIGNED;
+            ((-1f32).to_bits(), 1f32.to_bits())
+        } else {
+            (0, (1 << sample_bits) - 1)
+        };
+        descriptor.extend([
+            (channel * sample_bits) | ((sample_bits - 1) << 16) | (channel_type << 24),
+            0,
+            sample_lower,
+            sample_upper,
+        ]);
+    }
+    descriptor
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        alpha_premultiplied, set_alpha_premultiplied, transfer_function, uncompressed_descriptor,
+    };
+    use crate::{color_space::KHR_DF_TRANSFER_SRGB, source_image::PixelFormat};
+
+    #[test]
+    fn premultiplied_flag_keeps_the_transfer_function() {
+        let mut dfd = uncompressed_descriptor(PixelFormat::Rgba8, KHR_DF_TRANSFER_SRGB);
+        assert_eq!(dfd[0] as usize, dfd.len() * 4);
+        assert_eq!(alpha_premultiplied(&dfd), Some(false));
+
+        assert!(set_alpha_premultiplied(&mut dfd, true));
+        assert_eq!(alpha_premultiplied(&dfd), Some(true));
+        assert_eq!(transfer_function(&dfd), Some(KHR_DF_TRANSFER_SRGB));
+
+        assert!(set_alpha_premultiplied(&mut dfd, false));
+        assert_eq!(alpha_premultiplied(&dfd), Some(false));
+        assert!(!set_alpha_premultiplied(&mut [], true));
+    }
+}
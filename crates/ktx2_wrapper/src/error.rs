@@ -0,0 +1,299 @@
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    io,
+    path::{Path, PathBuf},
+};
+
+use shared_types::LocalError;
+
+#[cfg(feature = "libktx")]
+use crate::ffi::ktx_error_code_e;
+
+/// Without libktx nothing can produce a libktx error code, so the variants carrying one can never
+/// be built
+#[cfg(not(feature = "libktx"))]
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ktx_error_code_e {}
+
+#[cfg(not(feature = "libktx"))]
+impl From<ktx_error_code_e> for String {
+    fn from(code: ktx_error_code_e) -> Self {
+        match code {}
+    }
+}
+
+/// The step of building or reading a texture that failed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KtxStage {
+    Decode,
+    Create,
+    SetImage,
+    Compress,
+    Write,
+    Read,
+    Transcode,
+    Metadata,
+    Config,
+    Verify,
+}
+
+impl Display for KtxStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stage = match self {
+            Self::Decode => "decoding",
+            Self::Create => "creating",
+            Self::SetImage => "setting images of",
+            Self::Compress => "compressing",
+            Self::Write => "writing",
+            Self::Read => "reading",
+            Self::Transcode => "transcoding",
+            Self::Metadata => "setting metadata of",
+            Self::Config => "validating the config of",
+            Self::Verify => "verifying the quality of",
+        };
+        write!(f, "{stage}")
+    }
+}
+
+/// Errors returned by `KtxTexture` and the texture writing functions. Errors raised by libktx
+/// carry its error code, and every error carries the path of the file being processed once one
+/// is known
+#[derive(Debug)]
+pub enum KtxError {
+    /// The source image could not be read or decoded
+    Decode {
+        path: Option<PathBuf>,
+        source: LocalError,
+    },
+    Create {
+        path: Option<PathBuf>,
+        code: ktx_error_code_e,
+    },
+    SetImage {
+        path: Option<PathBuf>,
+        code: ktx_error_code_e,
+    },
+    Compress {
+        path: Option<PathBuf>,
+        code: ktx_error_code_e,
+    },
+    Write {
+        path: Option<PathBuf>,
+        code: ktx_error_code_e,
+    },
+    Read {
+        path: Option<PathBuf>,
+        code: ktx_error_code_e,
+    },
+    Transcode {
+        path: Option<PathBuf>,
+        code: ktx_error_code_e,
+    },
+    Metadata {
+        path: Option<PathBuf>,
+        code: ktx_error_code_e,
+    },
+    /// An image handed to `KtxTexture::set_image` is not the size of one image of its level
+    ImageSize {
+        path: Option<PathBuf>,
+        level: u32,
+        layer: u32,
+        face_slice: u32,
+        expected: usize,
+        actual: usize,
+    },
+    /// The encoded texture is below the thresholds of `CompressionConfig::verify`
+    Verify {
+        path: Option<PathBuf>,
+        reason: String,
+    },
+    /// The `CompressionConfig` or the requested texture layout cannot be used
+    InvalidConfig {
+        path: Option<PathBuf>,
+        reason: String,
+    },
+    Io {
+        stage: KtxStage,
+        path: Option<PathBuf>,
+        source: io::Error,
+    },
+}
+
+impl KtxError {
+    pub fn stage(&self) -> KtxStage {
+        match self {
+            Self::Decode { .. } => KtxStage::Decode,
+            Self::Create { .. } => KtxStage::Create,
+            Self::SetImage { .. } | Self::ImageSize { .. } => KtxStage::SetImage,
+            Self::Compress { .. } => KtxStage::Compress,
+            Self::Write { .. } => KtxStage::Write,
+            Self::Read { .. } => KtxStage::Read,
+            Self::Transcode { .. } => KtxStage::Transcode,
+            Self::Metadata { .. } => KtxStage::Metadata,
+            Self::InvalidConfig { .. } => KtxStage::Config,
+            Self::Verify { .. } => KtxStage::Verify,
+            Self::Io { stage, .. } => *stage,
+        }
+    }
+
+    /// The libktx error code, if the failure came from libktx
+    pub fn code(&self) -> Option<ktx_error_code_e> {
+        match self {
+            Self::Create { code, .. }
+            | Self::SetImage { code, .. }
+            | Self::Compress { code, .. }
+            | Self::Write { code, .. }
+            | Self::Read { code, .. }
+            | Self::Transcode { code, .. }
+            | Self::Metadata { code, .. } => Some(*code),
+            Self::Decode { .. }
+            | Self::ImageSize { .. }
+            | Self::Verify { .. }
+            | Self::InvalidConfig { .. }
+            | Self::Io { .. } => None,
+        }
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path_slot().as_deref()
+    }
+
+    /// Attaches `path` unless the error already names a file
+    pub fn with_path(mut self, path: &Path) -> Self {
+        let path_slot = self.path_slot_mut();
+        if path_slot.is_none() {
+            *path_slot = Some(path.to_path_buf());
+        }
+        self
+    }
+
+    pub fn invalid_config(reason: impl Into<String>) -> Self {
+        Self::InvalidConfig {
+            path: None,
+            reason: reason.into(),
+        }
+    }
+
+    fn path_slot(&self) -> &Option<PathBuf> {
+        match self {
+            Self::Decode { path, .. }
+            | Self::Create { path, .. }
+            | Self::SetImage { path, .. }
+            | Self::Compress { path, .. }
+            | Self::Write { path, .. }
+            | Self::Read { path, .. }
+            | Self::Transcode { path, .. }
+            | Self::Metadata { path, .. }
+            | Self::ImageSize { path, .. }
+            | Self::Verify { path, .. }
+            | Self::InvalidConfig { path, .. }
+            | Self::Io { path, .. } => path,
+        }
+    }
+
+    fn path_slot_mut(&mut self) -> &mut Option<PathBuf> {
+        match self {
+            Self::Decode { path, .. }
+            | Self::Create { path, .. }
+            | Self::SetImage { path, .. }
+            | Self::Compress { path, .. }
+            | Self::Write { path, .. }
+            | Self::Read { path, .. }
+            | Self::Transcode { path, .. }
+            | Self::Metadata { path, .. }
+            | Self::ImageSize { path, .. }
+            | Self::Verify { path, .. }
+            | Self::InvalidConfig { path, .. }
+            | Self::Io { path, .. } => path,
+        }
+    }
+}
+
+impl Display for KtxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Error {}", self.stage())?;
+        match self.path() {
+            Some(path) => write!(f, " {}", path.display())?,
+            None => write!(f, " texture")?,
+        }
+        match self {
+            Self::Decode { source, .. } => write!(f, ": {source}"),
+            Self::ImageSize {
+                level,
+                layer,
+                face_slice,
+                expected,
+                actual,
+                ..
+            } => write!(
+                f,
+                ": image {level}/{layer}/{face_slice} (level/layer/face or slice) needs {expected} bytes but {actual} were given"
+            ),
+            Self::InvalidConfig { reason, .. } | Self::Verify { reason, .. } => {
+                write!(f, ": {reason}")
+            }
+            Self::Io { source, .. } => write!(f, ": {source}"),
+            _ => match self.code() {
+                Some(code) => write!(f, ": {}", String::from(code)),
+                None => Ok(()),
+            },
+        }
+    }
+}
+
+impl Error for KtxError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Decode { source, .. } => Some(source.as_ref()),
+            Self::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl KtxError {
+    pub fn io(stage: KtxStage, path: &Path, source: io::Error) -> Self {
+        Self::Io {
+            stage,
+            path: Some(path.to_path_buf()),
+            source,
+        }
+    }
+}
+
+/// Turns a libktx result code into a `Result`, building the error with `error` on failure
+#[cfg(feature = "libktx")]
+pub(crate) fn check(
+    code: ktx_error_code_e,
+    error: fn(ktx_error_code_e) -> KtxError,
+) -> Result<(), KtxError> {
+    if code == ktx_error_code_e::KTX_SUCCESS {
+        Ok(())
+    } else {
+        Err(error(code))
+    }
+}
+
+macro_rules! create_code_constructors {
+    ($(($function_name:ident, $variant:ident)), *) => {
+        impl KtxError {
+            $(
+                pub fn $function_name(code: ktx_error_code_e) -> Self {
+                    Self::$variant { path: None, code }
+                }
+            )*
+        }
+    };
+}
+
+create_code_constructors!(
+    (create, Create),
+    (set_image, SetImage),
+    (compress, Compress),
+    (write, Write),
+    (read, Read),
+    (transcode, Transcode),
+    (metadata, Metadata)
+);
@@ -0,0 +1,9 @@
+#pragma once
+
+// VkFormat is declared in a private libktx header that installed copies of libktx don't ship, so
+// a system libktx takes it from the Vulkan headers instead. Both declare the same values
+#ifdef KTX2_WRAPPER_SYSTEM_LIBKTX
+#include <vulkan/vulkan_core.h>
+#else
+#include "vk_format.h"
+#endif
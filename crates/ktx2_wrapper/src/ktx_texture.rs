@@ -1,133 +1,255 @@
 use std::{
     borrow::BorrowMut,
-    fmt::Display,
+    ffi::{CStr, CString},
+    fs::{read, write},
+    io::Write,
     marker::PhantomData,
     mem::{replace, take},
     path::Path,
     ptr::null_mut,
+    slice::{from_raw_parts, from_raw_parts_mut},
 };
 
-use num_traits::Bounded;
-use serde::{Deserialize, Deserializer, Serialize};
+use cxx::UniquePtr;
 
 use crate::{
-    config::{CompressionConfig, KTXCompressionConfig},
+    color_space::ColorSpace,
+    config::{CompressionConfig, KTXCompressionConfig, Supercompression, ZLib, Zstd},
+    dfd,
+    error::{check, KtxError, KtxStage},
     ffi::{
-        self, ktxTexture2, ktxTexture2_CreateWrapped, ktxTextureCreateInfo,
-        ktxTextureCreateStorageEnum, ktxTexture_DestroyWrapped,
-        ktxTexture_SetImageFromMemoryWrapped, ktxTexture_WriteToNamedFileWrapped, ktx_error_code_e,
-        VkFormat,
+        self, ktxAstcParams, ktxFreeMemoryWrapped, ktxTexture2,
+        ktxTexture2_CreateFromMemoryWrapped, ktxTexture2_CreateWrapped,
+        ktxTexture2_DeleteKVWrapped, ktxTexture2_GetDfdWrapped, ktxTexture2_GetImageWrapped,
+        ktxTexture2_GetInfoWrapped, ktxTexture2_GetKVCountWrapped, ktxTexture2_GetKVKeyWrapped,
+        ktxTexture2_GetKVValueWrapped, ktxTexture2_NeedsTranscoding, ktxTexture2_SetKVWrapped,
+        ktxTexture2_TranscodeBasis, ktxTextureCreateInfo, ktxTextureCreateStorageEnum,
+        ktxTexture_DestroyWrapped, ktxTexture_SetImageFromMemoryWrapped,
+        ktxTexture_WriteToMemoryWrapped, ktx_error_code_e, VkFormat,
     },
+    ffi2::KtxTextureInfo,
+    ktx_types::TranscodeTarget,
+    metadata::{self, MetadataValue, KTX_ORIENTATION_KEY, KTX_SWIZZLE_KEY, KTX_WRITER_KEY},
+};
+pub use crate::{
+    deflation::{ZLibDeflationValue, ZstdDeflationValue},
+    texture_shape::TextureShape,
 };
 
-#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq, PartialOrd, Ord)]
-pub struct ZLibDeflationValue(u8);
+pub struct Uninitialized;
+pub struct BeforeImageSetInMemory;
+pub struct ImageSetInMemory;
+pub struct Loaded;
 
-impl Display for ZLibDeflationValue {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
-    }
-}
+/// Stages whose images are all set, so the texture can be serialized
+pub trait WritableStage {}
 
-impl<'a> Deserialize<'a> for ZLibDeflationValue {
-    fn deserialize<D: Deserializer<'a>>(deserializer: D) -> Result<Self, D::Error> {
-        let u8_value = u8::deserialize(deserializer)?;
+impl WritableStage for ImageSetInMemory {}
+impl WritableStage for Loaded {}
 
-        Ok(u8_value.into())
-    }
+pub struct KtxTexture<Stage> {
+    ktx_texture_2_ptr: *mut ktxTexture2,
+    texture_create_info: ktxTextureCreateInfo,
+    images_set: Vec<bool>,
+    phantom_marker: PhantomData<Stage>,
 }
 
-impl Default for ZLibDeflationValue {
-    fn default() -> Self {
-        Self(7)
+impl<Stage> KtxTexture<Stage> {
+    fn level_slice_count(&self, level: u32) -> u32 {
+        let depth = (self.texture_create_info.baseDepth >> level).max(1);
+        self.texture_create_info.numLayers * self.texture_create_info.numFaces * depth
     }
-}
 
-impl Bounded for ZLibDeflationValue {
-    fn min_value() -> Self {
-        Self(1)
+    fn image_count(&self) -> usize {
+        (0..self.texture_create_info.numLevels)
+            .map(|level| self.level_slice_count(level) as usize)
+            .sum()
     }
 
-    fn max_value() -> Self {
-        Self(9)
+    /// Index into `images_set` for an image, or None if the image is outside of the texture. For
+    /// volume textures `face_slice` is the depth slice, otherwise it is the cubemap face
+    fn image_index(&self, level: u32, layer: u32, face_slice: u32) -> Option<usize> {
+        let create_info = &self.texture_create_info;
+        let slices_per_layer = create_info.numFaces * (create_info.baseDepth >> level).max(1);
+        if level >= create_info.numLevels
+            || layer >= create_info.numLayers
+            || face_slice >= slices_per_layer
+        {
+            return None;
+        }
+        let previous_levels = (0..level)
+            .map(|previous_level| self.level_slice_count(previous_level) as usize)
+            .sum::<usize>();
+        Some(previous_levels + (layer * slices_per_layer + face_slice) as usize)
     }
 }
 
-impl From<u8> for ZLibDeflationValue {
-    fn from(value: u8) -> Self {
-        Self(value).clamp(Self::min_value(), Self::max_value())
+impl<Stage> KtxTexture<Stage> {
+    /// The raw data format descriptor, starting with the total size word
+    pub fn dfd(&self) -> &[u32] {
+        let dfd_ptr = unsafe { ktxTexture2_GetDfdWrapped(self.ktx_texture_2_ptr) };
+        if dfd_ptr.is_null() {
+            return &[];
+        }
+        unsafe {
+            let dfd_byte_size = *dfd_ptr;
+            from_raw_parts(dfd_ptr, dfd_byte_size as usize / size_of::<u32>())
+        }
     }
-}
 
-impl From<ZLibDeflationValue> for u32 {
-    fn from(value: ZLibDeflationValue) -> Self {
-        value.0 as u32
+    fn dfd_mut(&mut self) -> &mut [u32] {
+        let dfd_ptr = unsafe { ktxTexture2_GetDfdWrapped(self.ktx_texture_2_ptr) };
+        if dfd_ptr.is_null() {
+            return &mut [];
+        }
+        unsafe {
+            let dfd_byte_size = *dfd_ptr;
+            from_raw_parts_mut(dfd_ptr, dfd_byte_size as usize / size_of::<u32>())
+        }
     }
-}
-#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq, PartialOrd, Ord)]
-pub struct ZstdDeflationValue(u8);
 
-impl Display for ZstdDeflationValue {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
+    /// The color space signalled by the transfer function of the data format descriptor
+    pub fn color_space(&self) -> Option<ColorSpace> {
+        dfd::transfer_function(self.dfd()).and_then(ColorSpace::from_transfer_function)
     }
-}
 
-impl<'a> Deserialize<'a> for ZstdDeflationValue {
-    fn deserialize<D: Deserializer<'a>>(deserializer: D) -> Result<Self, D::Error> {
-        let u8_value = u8::deserialize(deserializer)?;
+    /// Whether the data format descriptor flags the color channels as premultiplied by alpha
+    pub fn alpha_premultiplied(&self) -> bool {
+        dfd::alpha_premultiplied(self.dfd()).unwrap_or(false)
+    }
 
-        Ok(u8_value.into())
+    /// Sets or clears `KHR_DF_FLAG_ALPHA_PREMULTIPLIED`. Compressing rebuilds the descriptor, so
+    /// this must be called again after `set_compression`
+    pub fn set_alpha_premultiplied(&mut self, premultiplied: bool) -> Result<(), KtxError> {
+        if dfd::set_alpha_premultiplied(self.dfd_mut(), premultiplied) {
+            Ok(())
+        } else {
+            Err(KtxError::metadata(ktx_error_code_e::KTX_INVALID_OPERATION))
+        }
     }
-}
 
-impl Default for ZstdDeflationValue {
-    fn default() -> Self {
-        Self(17)
+    /// Every key/value pair of the texture, in file order. Values are returned as stored, so string
+    /// values keep their NUL terminator
+    pub fn key_values(&self) -> Vec<(String, Vec<u8>)> {
+        let kv_count = unsafe { ktxTexture2_GetKVCountWrapped(self.ktx_texture_2_ptr) };
+        (0..kv_count)
+            .filter_map(|index| {
+                let key_ptr = unsafe { ktxTexture2_GetKVKeyWrapped(self.ktx_texture_2_ptr, index) };
+                if key_ptr.is_null() {
+                    return None;
+                }
+                let key = unsafe { CStr::from_ptr(key_ptr) }
+                    .to_string_lossy()
+                    .into_owned();
+                let mut value_length = 0;
+                let value_ptr = unsafe {
+                    ktxTexture2_GetKVValueWrapped(self.ktx_texture_2_ptr, index, &mut value_length)
+                };
+                let value = if value_ptr.is_null() {
+                    vec![]
+                } else {
+                    unsafe { from_raw_parts(value_ptr, value_length as usize) }.to_vec()
+                };
+                Some((key, value))
+            })
+            .collect()
     }
-}
 
-impl Bounded for ZstdDeflationValue {
-    fn min_value() -> Self {
-        Self(1)
+    /// The value stored under `key`, if any
+    pub fn metadata(&self, key: &str) -> Option<MetadataValue> {
+        self.key_values()
+            .into_iter()
+            .find(|(existing_key, _)| existing_key == key)
+            .map(|(_, value)| MetadataValue::from_bytes(&value))
     }
 
-    fn max_value() -> Self {
-        Self(22)
+    /// Stores `value` under `key`, replacing any previous value. Use the typed setters for the
+    /// standard `KTX` keys, they are rejected here
+    pub fn set_metadata(&mut self, key: &str, value: &MetadataValue) -> Result<(), KtxError> {
+        if metadata::is_reserved_key(key) {
+            return Err(KtxError::metadata(ktx_error_code_e::KTX_INVALID_VALUE));
+        }
+        self.set_key_value(key, &value.to_bytes())
     }
-}
 
-impl From<u8> for ZstdDeflationValue {
-    fn from(value: u8) -> Self {
-        Self(value).clamp(Self::min_value(), Self::max_value())
+    pub fn remove_metadata(&mut self, key: &str) -> Result<(), KtxError> {
+        let Ok(key) = CString::new(key) else {
+            return Err(KtxError::metadata(ktx_error_code_e::KTX_INVALID_VALUE));
+        };
+        let result = unsafe { ktxTexture2_DeleteKVWrapped(self.ktx_texture_2_ptr, key.as_ptr()) };
+        check(result, KtxError::metadata)
     }
-}
 
-impl From<ZstdDeflationValue> for u32 {
-    fn from(value: ZstdDeflationValue) -> Self {
-        value.0 as u32
+    /// Sets `KTXorientation`, e.g. `rd` for x right and y down
+    pub fn set_orientation(&mut self, orientation: &str) -> Result<(), KtxError> {
+        if !metadata::is_valid_orientation(orientation) {
+            return Err(KtxError::metadata(ktx_error_code_e::KTX_INVALID_VALUE));
+        }
+        self.set_key_value(
+            KTX_ORIENTATION_KEY,
+            &MetadataValue::from(orientation).to_bytes(),
+        )
     }
-}
 
-pub struct Uninitialized;
-pub struct BeforeImageSetInMemory;
-pub struct ImageSetInMemory;
+    /// Sets `KTXwriter`. libktx appends its own version when the texture is written
+    pub fn set_writer(&mut self, writer: &str) -> Result<(), KtxError> {
+        self.set_key_value(KTX_WRITER_KEY, &MetadataValue::from(writer).to_bytes())
+    }
 
-pub struct KtxTexture<Stage> {
-    ktx_texture_2_ptr: *mut ktxTexture2,
-    texture_create_info: ktxTextureCreateInfo,
-    phantom_marker: PhantomData<Stage>,
+    /// Sets `KTXswizzle`, the component mapping a loader should apply, e.g. `rgb1`
+    pub fn set_swizzle(&mut self, swizzle: &str) -> Result<(), KtxError> {
+        if !metadata::is_valid_swizzle(swizzle) {
+            return Err(KtxError::metadata(ktx_error_code_e::KTX_INVALID_VALUE));
+        }
+        self.set_key_value(KTX_SWIZZLE_KEY, &MetadataValue::from(swizzle).to_bytes())
+    }
+
+    fn set_key_value(&mut self, key: &str, value: &[u8]) -> Result<(), KtxError> {
+        let Ok(key) = CString::new(key) else {
+            return Err(KtxError::metadata(ktx_error_code_e::KTX_INVALID_VALUE));
+        };
+        let Ok(value_length) = u32::try_from(value.len()) else {
+            return Err(KtxError::metadata(ktx_error_code_e::KTX_INVALID_VALUE));
+        };
+        let result = unsafe {
+            ktxTexture2_SetKVWrapped(
+                self.ktx_texture_2_ptr,
+                key.as_ptr(),
+                value_length,
+                value.as_ptr(),
+            )
+        };
+        check(result, KtxError::metadata)
+    }
 }
 
 impl KtxTexture<Uninitialized> {
     pub fn new(
         width: u32,
         height: u32,
+        num_levels: u32,
+        format: VkFormat,
+    ) -> Result<KtxTexture<BeforeImageSetInMemory>, KtxError> {
+        Self::new_with_shape(width, height, num_levels, TextureShape::Texture2D, format)
+    }
+
+    pub fn new_with_shape(
+        width: u32,
+        height: u32,
+        num_levels: u32,
+        shape: TextureShape,
         format: VkFormat,
-    ) -> Result<KtxTexture<BeforeImageSetInMemory>, ktx_error_code_e> {
+    ) -> Result<KtxTexture<BeforeImageSetInMemory>, KtxError> {
+        if shape.num_faces() == 6 && width != height {
+            return Err(KtxError::invalid_config(format!(
+                "cubemap faces must be square, got {width}x{height}"
+            )));
+        }
+
         let mut result = ktx_error_code_e::KTX_FILE_DATA_ERROR;
 
-        let mut texture_create_info = ktxTextureCreateInfo::new(width, height, format);
+        let mut texture_create_info =
+            ktxTextureCreateInfo::new(width, height, num_levels, format).with_shape(&shape);
 
         let ktx_texture_2_ptr = unsafe {
             ktxTexture2_CreateWrapped(
@@ -138,51 +260,110 @@ impl KtxTexture<Uninitialized> {
         };
 
         if result == ktx_error_code_e::KTX_SUCCESS {
-            Ok(KtxTexture {
+            let mut ktx_texture = KtxTexture {
                 ktx_texture_2_ptr,
                 texture_create_info,
+                images_set: vec![],
                 phantom_marker: PhantomData,
-            })
+            };
+            ktx_texture.images_set = vec![false; ktx_texture.image_count()];
+            Ok(ktx_texture)
         } else {
-            Err(result)
+            Err(KtxError::create(result))
         }
     }
 }
 
 impl KtxTexture<BeforeImageSetInMemory> {
-    /// # Safety
-    /// `image_data` is a raw pointer being passed into an outside library, and could have undefined behavior
-    pub unsafe fn set_image_in_memory(
-        mut self,
-        image_data: *mut u8,
-    ) -> Result<KtxTexture<ImageSetInMemory>, ktx_error_code_e> {
+    /// Sets a single image of the texture. For volume textures `face_slice` is the depth slice,
+    /// otherwise it is the cubemap face. `image_data` must hold exactly one image of `level`
+    pub fn set_image(
+        &mut self,
+        level: u32,
+        layer: u32,
+        face_slice: u32,
+        image_data: &[u8],
+    ) -> Result<(), KtxError> {
+        let image_index = self
+            .image_index(level, layer, face_slice)
+            .ok_or(KtxError::set_image(ktx_error_code_e::KTX_INVALID_VALUE))?;
+        let expected_len = self.texture_create_info.level_image_size(level);
+        if image_data.len() != expected_len {
+            return Err(KtxError::ImageSize {
+                path: None,
+                level,
+                layer,
+                face_slice,
+                expected: expected_len,
+                actual: image_data.len(),
+            });
+        }
         let result = unsafe {
             ktxTexture_SetImageFromMemoryWrapped(
                 self.ktx_texture_2_ptr,
-                0,
-                0,
-                0,
-                image_data,
-                self.texture_create_info.image_size(),
+                level,
+                layer,
+                face_slice,
+                image_data.as_ptr(),
+                expected_len,
             )
         };
-        if result == ktx_error_code_e::KTX_SUCCESS {
-            Ok(KtxTexture {
-                ktx_texture_2_ptr: replace(&mut self.ktx_texture_2_ptr, null_mut()),
-                texture_create_info: take(&mut self.texture_create_info),
-                phantom_marker: PhantomData,
-            })
+        check(result, KtxError::set_image)?;
+        self.images_set[image_index] = true;
+        Ok(())
+    }
+
+    /// Sets one of the mip levels below the base level of a 2D texture, the base level is set by
+    /// `set_base_image`
+    pub fn set_level(&mut self, level: u32, image_data: &[u8]) -> Result<(), KtxError> {
+        if level == 0 {
+            return Err(KtxError::set_image(ktx_error_code_e::KTX_INVALID_VALUE));
+        }
+        self.set_image(level, 0, 0, image_data)
+    }
+
+    /// Sets the base level of a 2D texture and finishes setting images
+    pub fn set_base_image(
+        mut self,
+        image_data: &[u8],
+    ) -> Result<KtxTexture<ImageSetInMemory>, KtxError> {
+        self.set_image(0, 0, 0, image_data)?;
+        self.finish_setting_images()
+    }
+
+    /// Writes the transfer function matching `color_space` into the data format descriptor. The
+    /// Basis Universal encoders pick perceptual or linear error metrics from it
+    pub fn set_color_space(&mut self, color_space: ColorSpace) -> Result<(), KtxError> {
+        if dfd::set_transfer_function(self.dfd_mut(), color_space.transfer_function()) {
+            Ok(())
         } else {
-            Err(result)
+            Err(KtxError::create(ktx_error_code_e::KTX_INVALID_OPERATION))
         }
     }
+
+    /// Moves on to compression once every (level, layer, face) image has been set, an image that was
+    /// never set would otherwise be compressed and written as uninitialized memory
+    pub fn finish_setting_images(mut self) -> Result<KtxTexture<ImageSetInMemory>, KtxError> {
+        if self.images_set.iter().any(|image_set| !image_set) {
+            return Err(KtxError::set_image(ktx_error_code_e::KTX_INVALID_OPERATION));
+        }
+        Ok(KtxTexture {
+            ktx_texture_2_ptr: replace(&mut self.ktx_texture_2_ptr, null_mut()),
+            texture_create_info: take(&mut self.texture_create_info),
+            images_set: take(&mut self.images_set),
+            phantom_marker: PhantomData,
+        })
+    }
 }
 
 impl KtxTexture<ImageSetInMemory> {
     pub fn set_compression(
         &mut self,
         compression_config: &CompressionConfig,
-    ) -> Result<(), ktx_error_code_e> {
+    ) -> Result<(), KtxError> {
+        compression_config
+            .validate()
+            .map_err(|err| KtxError::invalid_config(err.to_string()))?;
         match compression_config.config() {
             KTXCompressionConfig::BasisUniversalBasisLZETC1s(basis_universal_basis_lzetc1s) => {
                 let (base_config, etc1s_config) = basis_universal_basis_lzetc1s.into();
@@ -195,9 +376,7 @@ impl KtxTexture<ImageSetInMemory> {
                     )
                 };
 
-                if result != ktx_error_code_e::KTX_SUCCESS {
-                    return Err(result);
-                }
+                check(result, KtxError::compress)?;
             }
             KTXCompressionConfig::BasisUniversalUASTC(basis_universal_uastc) => {
                 let (base_config, uastc_config) = basis_universal_uastc.into();
@@ -210,64 +389,168 @@ impl KtxTexture<ImageSetInMemory> {
                     )
                 };
 
-                if result != ktx_error_code_e::KTX_SUCCESS {
-                    return Err(result);
-                }
+                check(result, KtxError::compress)?;
             }
             KTXCompressionConfig::ASTC(astc) => {
-                let config = astc.into();
+                if astc.has_3d_block_dimension() && self.texture_create_info.baseDepth <= 1 {
+                    return Err(KtxError::invalid_config(
+                        "3D ASTC block dimensions can only be used for volume textures",
+                    ));
+                }
+                let mut config: UniquePtr<ktxAstcParams> = astc.into();
+                if astc.perceptual.is_none() {
+                    config.perceptual = self.color_space() == Some(ColorSpace::Srgb);
+                }
 
                 let result = unsafe {
                     ffi::ktxTexture2_CompressAstcExWrapped(self.ktx_texture_2_ptr, config)
                 };
 
-                if result != ktx_error_code_e::KTX_SUCCESS {
-                    return Err(result);
-                }
+                check(result, KtxError::compress)?;
             }
-            KTXCompressionConfig::ZLib(zlib) => {
-                let result = unsafe {
-                    ffi::ktxTexture2_DeflateZLIB(
-                        self.ktx_texture_2_ptr,
-                        zlib.deflation_value.into(),
-                    )
-                };
+            KTXCompressionConfig::ZLib(zlib) => self.deflate_zlib(zlib)?,
+            KTXCompressionConfig::Zstd(zstd) => self.deflate_zstd(zstd)?,
+        }
+        match compression_config.supercompression() {
+            Some(Supercompression::ZLib(zlib)) => self.deflate_zlib(zlib),
+            Some(Supercompression::Zstd(zstd)) => self.deflate_zstd(zstd),
+            None => Ok(()),
+        }
+    }
 
-                if result != ktx_error_code_e::KTX_SUCCESS {
-                    return Err(result);
-                }
-            }
-            KTXCompressionConfig::Zstd(zstd) => {
-                let result = unsafe {
-                    ffi::ktxTexture2_DeflateZstd(
-                        self.ktx_texture_2_ptr,
-                        zstd.deflation_value.into(),
-                    )
-                };
+    fn deflate_zlib(&mut self, zlib: &ZLib) -> Result<(), KtxError> {
+        let result = unsafe {
+            ffi::ktxTexture2_DeflateZLIB(self.ktx_texture_2_ptr, zlib.deflation_value.into())
+        };
 
-                if result != ktx_error_code_e::KTX_SUCCESS {
-                    return Err(result);
-                }
-            }
+        check(result, KtxError::compress)
+    }
+
+    fn deflate_zstd(&mut self, zstd: &Zstd) -> Result<(), KtxError> {
+        let result = unsafe {
+            ffi::ktxTexture2_DeflateZstd(self.ktx_texture_2_ptr, zstd.deflation_value.into())
+        };
+
+        check(result, KtxError::compress)
+    }
+}
+
+impl<Stage: WritableStage> KtxTexture<Stage> {
+    pub fn write_image_to_disk(&self, path: &Path) -> Result<(), KtxError> {
+        let bytes = self.write_to_vec().map_err(|err| err.with_path(path))?;
+        write(path, bytes).map_err(|err| KtxError::io(KtxStage::Write, path, err))
+    }
+
+    /// Serializes the texture to a KTX2 file in memory
+    pub fn write_to_vec(&self) -> Result<Vec<u8>, KtxError> {
+        let mut result = ktx_error_code_e::KTX_FILE_DATA_ERROR;
+        let mut size = 0;
+        let bytes_ptr = unsafe {
+            ktxTexture_WriteToMemoryWrapped(self.ktx_texture_2_ptr, &mut size, &mut result)
+        };
+        check(result, KtxError::write)?;
+        if bytes_ptr.is_null() {
+            return Err(KtxError::write(ktx_error_code_e::KTX_OUT_OF_MEMORY));
         }
-        Ok(())
+        let bytes = unsafe { from_raw_parts(bytes_ptr, size) }.to_vec();
+        unsafe { ktxFreeMemoryWrapped(bytes_ptr) };
+        Ok(bytes)
     }
-    pub fn write_image_to_disk(&self, path: &Path) -> Result<(), ktx_error_code_e> {
-        let path = std::ffi::CString::new(path.to_str().unwrap()).unwrap();
-        let result =
-            unsafe { ktxTexture_WriteToNamedFileWrapped(self.ktx_texture_2_ptr, path.as_ptr()) };
+
+    /// Serializes the texture into `writer`. This does not stream: the whole file is built in
+    /// memory with `write_to_vec` first and then written in one go
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), KtxError> {
+        writer
+            .write_all(&self.write_to_vec()?)
+            .map_err(|source| KtxError::Io {
+                stage: KtxStage::Write,
+                path: None,
+                source,
+            })
+    }
+}
+
+impl KtxTexture<Loaded> {
+    /// Reads a KTX2 file from disk, loading the image data of every level
+    pub fn open(path: &Path) -> Result<Self, KtxError> {
+        let file_contents = read(path).map_err(|err| KtxError::io(KtxStage::Read, path, err))?;
+        Self::from_bytes(&file_contents).map_err(|err| err.with_path(path))
+    }
+
+    /// Reads a KTX2 file from memory, the bytes are copied so they don't need to outlive the texture
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, KtxError> {
+        let mut result = ktx_error_code_e::KTX_FILE_DATA_ERROR;
+
+        let ktx_texture_2_ptr = unsafe {
+            ktxTexture2_CreateFromMemoryWrapped(bytes.as_ptr(), bytes.len(), &mut result)
+        };
+
         if result == ktx_error_code_e::KTX_SUCCESS {
-            Ok(())
+            let mut ktx_texture = KtxTexture {
+                ktx_texture_2_ptr,
+                texture_create_info: ktxTextureCreateInfo::default(),
+                images_set: vec![],
+                phantom_marker: PhantomData,
+            };
+            ktx_texture.texture_create_info = (&ktx_texture.info()).into();
+            Ok(ktx_texture)
+        } else {
+            Err(KtxError::read(result))
+        }
+    }
+
+    /// Header values of the texture: format, dimensions, level/layer/face counts and supercompression
+    pub fn info(&self) -> KtxTextureInfo {
+        let mut info = KtxTextureInfo::default();
+        unsafe { ktxTexture2_GetInfoWrapped(self.ktx_texture_2_ptr, &mut info) };
+        info
+    }
+
+    /// Whether the texture is Basis Universal encoded and must be transcoded before its images
+    /// can be read
+    pub fn needs_transcoding(&self) -> bool {
+        unsafe { ktxTexture2_NeedsTranscoding(self.ktx_texture_2_ptr) }
+    }
+
+    /// Transcodes a Basis Universal texture in place, afterwards the images are in `target` format
+    pub fn transcode(&mut self, target: TranscodeTarget) -> Result<(), KtxError> {
+        if !self.needs_transcoding() {
+            return Err(KtxError::transcode(ktx_error_code_e::KTX_INVALID_OPERATION));
+        }
+        let result =
+            unsafe { ktxTexture2_TranscodeBasis(self.ktx_texture_2_ptr, target.into(), 0) };
+        check(result, KtxError::transcode)?;
+        self.texture_create_info = (&self.info()).into();
+        Ok(())
+    }
+
+    /// The data of a single image. For volume textures `face_slice` is the depth slice, otherwise
+    /// it is the cubemap face. Basis Universal textures must be transcoded first
+    pub fn image_data(&self, level: u32, layer: u32, face_slice: u32) -> Result<&[u8], KtxError> {
+        let mut result = ktx_error_code_e::KTX_FILE_DATA_ERROR;
+        let mut image_size = 0;
+        let image_ptr = unsafe {
+            ktxTexture2_GetImageWrapped(
+                self.ktx_texture_2_ptr,
+                level,
+                layer,
+                face_slice,
+                &mut image_size,
+                &mut result,
+            )
+        };
+        if result == ktx_error_code_e::KTX_SUCCESS && !image_ptr.is_null() {
+            Ok(unsafe { from_raw_parts(image_ptr, image_size) })
         } else {
-            Err(result)
+            Err(KtxError::read(result))
         }
     }
 }
 
-// Normally, we would only specify this trait for the ImageSetInMemory generic, however Drop is a special
-// trait that cannot be specialized. Further, run time type inspection/specialization isn't close to stable,
-// So instead we use some logic to deduce which stage the KtxTexture is at (the only time the ptr should not be
-// null is on the ImageSetInMemory stage/generic)
+// Drop is a special trait that cannot be specialized, and run time type inspection/specialization isn't close
+// to stable, so it is implemented for every stage. BeforeImageSetInMemory, ImageSetInMemory and Loaded textures
+// own a ktxTexture2, Uninitialized is never constructed, and moving to the next stage leaves a null ptr behind,
+// so the ptr is only destroyed by whichever stage owns it last
 impl<Stage> Drop for KtxTexture<Stage> {
     fn drop(&mut self) {
         if !self.ktx_texture_2_ptr.is_null() {
@@ -277,3 +560,26 @@ impl<Stage> Drop for KtxTexture<Stage> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{KtxTexture, Uninitialized};
+    use crate::{error::KtxError, ffi::VkFormat};
+
+    #[test]
+    fn set_image_checks_the_buffer_length() {
+        let mut ktx_texture =
+            KtxTexture::<Uninitialized>::new(2, 2, 1, VkFormat::VK_FORMAT_R8G8B8A8_UNORM).unwrap();
+        assert!(matches!(
+            ktx_texture.set_image(0, 0, 0, &[0; 15]),
+            Err(KtxError::ImageSize {
+                expected: 16,
+                actual: 15,
+                ..
+            })
+        ));
+        assert!(ktx_texture.set_image(1, 0, 0, &[0; 4]).is_err());
+        ktx_texture.set_image(0, 0, 0, &[0; 16]).unwrap();
+        assert!(ktx_texture.finish_setting_images().is_ok());
+    }
+}
@@ -1,18 +1,18 @@
-use std::{
-    fmt::{Debug, Display},
-    ptr::null,
-};
+use std::fmt::{Debug, Display};
 
+use serde::{Deserialize, Serialize};
 use shared_types::LocalError;
-use strum::Display;
+use strum::{Display, EnumIter, EnumString};
 
 use crate::{
     ffi::{
         ktxTextureCreateInfo, ktx_error_code_e, ktx_pack_astc_block_dimension_e,
-        ktx_pack_astc_encoder_mode_e, ktx_pack_astc_quality_levels_e, KtxBasisETC1SParams,
-        KtxBasisUASTCParams, VkFormat,
+        ktx_pack_astc_encoder_mode_e, ktx_pack_astc_quality_levels_e, ktx_transcode_fmt_e,
+        VkFormat,
     },
-    ffi2::{KtxBasisParams, KtxPackUastcFlags},
+    ffi2::{KtxPackUastcFlags, KtxTextureInfo, OptionalBool, OptionalF32, OptionalU32},
+    ktx_texture::TextureShape,
+    mipmaps::level_dimensions,
 };
 
 impl Copy for ktx_pack_astc_block_dimension_e {}
@@ -433,51 +433,35 @@ impl KtxPackUastcFlags {
     }
 }
 
-impl Default for KtxBasisETC1SParams {
-    fn default() -> Self {
-        Self {
-            compression_level: null(),
-            quality_level: null(),
-            max_endpoints: null(),
-            endpoint_rdo_threshold: null(),
-            max_selectors: null(),
-            selector_rdo_threshold: null(),
-            input_swizzle: null(),
-            normal_map: null(),
-            separate_rgt_to_rgba: null(),
-            pre_swizzle: null(),
-            no_endpoint_rdo: null(),
-            no_selector_rdo: null(),
-        }
-    }
-}
+macro_rules! create_optional_froms {
+    ($(($optional_type:ty, $value_type:ty)), *) => {
+        $(
+            impl From<Option<$value_type>> for $optional_type {
+                fn from(value: Option<$value_type>) -> Self {
+                    match value {
+                        Some(value) => Self { is_set: true, value },
+                        None => Self::default(),
+                    }
+                }
+            }
 
-impl Default for KtxBasisParams {
-    fn default() -> Self {
-        Self {
-            struct_size: null(),
-            uastc: false,
-            verbose: null(),
-            no_sse: null(),
-            thread_count: null(),
-        }
-    }
+            impl From<$optional_type> for Option<$value_type> {
+                fn from(value: $optional_type) -> Self {
+                    value.is_set.then_some(value.value)
+                }
+            }
+
+        )*
+    };
 }
 
-impl Default for KtxBasisUASTCParams {
-    fn default() -> Self {
-        Self {
-            uastc_flags: null(),
-            uastc_rdo: null(),
-            uastc_rdo_quality_scalar: null(),
-            uastc_rdo_dict_size: null(),
-            uastc_rdo_max_smooth_block_error_scale: null(),
-            uastc_rdo_max_smooth_block_std_dev: null(),
-            uastc_rdo_dont_favor_simpler_modes: null(),
-            uastc_rdo_no_multithreading: null(),
-            input_swizzle: null(),
-            pre_swizzle: null(),
-        }
+create_optional_froms!((OptionalU32, u32), (OptionalF32, f32), (OptionalBool, bool));
+
+impl Copy for ktx_error_code_e {}
+
+impl Debug for ktx_error_code_e {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", String::from(*self))
     }
 }
 
@@ -510,18 +494,135 @@ impl From<ktx_error_code_e> for String {
     }
 }
 
+/// GPU formats a Basis Universal texture can be transcoded to
+#[derive(
+    Copy, Clone, Debug, Display, EnumIter, EnumString, Serialize, Deserialize, PartialEq, Eq,
+)]
+#[strum(ascii_case_insensitive)]
+pub enum TranscodeTarget {
+    Rgba32,
+    Bc1,
+    Bc3,
+    Bc7,
+    Etc1,
+    Etc2,
+    Astc4x4,
+}
+
+impl From<TranscodeTarget> for ktx_transcode_fmt_e {
+    fn from(value: TranscodeTarget) -> Self {
+        match value {
+            TranscodeTarget::Rgba32 => ktx_transcode_fmt_e::KTX_TTF_RGBA32,
+            TranscodeTarget::Bc1 => ktx_transcode_fmt_e::KTX_TTF_BC1_RGB,
+            TranscodeTarget::Bc3 => ktx_transcode_fmt_e::KTX_TTF_BC3_RGBA,
+            TranscodeTarget::Bc7 => ktx_transcode_fmt_e::KTX_TTF_BC7_RGBA,
+            TranscodeTarget::Etc1 => ktx_transcode_fmt_e::KTX_TTF_ETC1_RGB,
+            TranscodeTarget::Etc2 => ktx_transcode_fmt_e::KTX_TTF_ETC2_RGBA,
+            TranscodeTarget::Astc4x4 => ktx_transcode_fmt_e::KTX_TTF_ASTC_4x4_RGBA,
+        }
+    }
+}
+
+/// Values of the supercompressionScheme field in the KTX2 header
+#[derive(Copy, Clone, Debug, Display, PartialEq, Eq)]
+pub enum SupercompressionScheme {
+    None,
+    BasisLZ,
+    Zstd,
+    ZLib,
+    Unknown(u32),
+}
+
+impl From<u32> for SupercompressionScheme {
+    fn from(value: u32) -> Self {
+        match value {
+            0 => Self::None,
+            1 => Self::BasisLZ,
+            2 => Self::Zstd,
+            3 => Self::ZLib,
+            unknown_value => Self::Unknown(unknown_value),
+        }
+    }
+}
+
+impl KtxTextureInfo {
+    pub fn supercompression(&self) -> SupercompressionScheme {
+        self.supercompression_scheme.into()
+    }
+}
+
+impl From<&KtxTextureInfo> for ktxTextureCreateInfo {
+    fn from(value: &KtxTextureInfo) -> Self {
+        Self {
+            vkFormat: value.vk_format,
+            baseWidth: value.base_width,
+            baseHeight: value.base_height,
+            baseDepth: value.base_depth,
+            numDimensions: value.num_dimensions,
+            numLevels: value.num_levels,
+            numLayers: value.num_layers,
+            numFaces: value.num_faces,
+            isArray: value.is_array,
+            ..Default::default()
+        }
+    }
+}
+
 impl ktxTextureCreateInfo {
-    pub fn new(width: u32, height: u32, format: VkFormat) -> Self {
+    pub fn new(width: u32, height: u32, num_levels: u32, format: VkFormat) -> Self {
         Self {
             baseWidth: width,
             baseHeight: height,
             vkFormat: format as u32,
+            numLevels: num_levels.max(1),
             ..Default::default()
         }
     }
 
+    pub fn with_shape(self, shape: &TextureShape) -> Self {
+        Self {
+            baseDepth: shape.depth(),
+            numDimensions: if shape.depth() > 1 { 3 } else { 2 },
+            numLayers: shape.num_layers(),
+            numFaces: shape.num_faces(),
+            isArray: shape.is_array(),
+            ..self
+        }
+    }
+
     pub fn image_size(&self) -> usize {
-        (self.baseWidth * self.baseHeight * 4) as usize
+        self.level_image_size(0)
+    }
+
+    pub fn level_image_size(&self, level: u32) -> usize {
+        let (width, height) = level_dimensions(self.baseWidth, self.baseHeight, level);
+        (width * height) as usize * self.bytes_per_pixel()
+    }
+
+    /// Size of an uncompressed pixel for the formats images are uploaded in
+    pub fn bytes_per_pixel(&self) -> usize {
+        let is_format =
+            |formats: &[VkFormat]| formats.iter().any(|format| *format as u32 == self.vkFormat);
+        if is_format(&[VkFormat::VK_FORMAT_R8_UNORM, VkFormat::VK_FORMAT_R8_SRGB]) {
+            1
+        } else if is_format(&[
+            VkFormat::VK_FORMAT_R8G8_UNORM,
+            VkFormat::VK_FORMAT_R8G8_SRGB,
+        ]) {
+            2
+        } else if is_format(&[
+            VkFormat::VK_FORMAT_R8G8B8_UNORM,
+            VkFormat::VK_FORMAT_R8G8B8_SRGB,
+        ]) {
+            3
+        } else if is_format(&[
+            VkFormat::VK_FORMAT_R16G16B16A16_UNORM,
+            VkFormat::VK_FORMAT_R16G16B16A16_SFLOAT,
+        ]) {
+            8
+        } else {
+            4
+        }
     }
 }
 
@@ -1,6 +1,8 @@
 #include "ktx_wrappers.h"
 #include "cxxgen1.h"
 
+#include <cstdlib>
+
 ktxTexture2*
 ktxTexture2_CreateWrapped(
     ktxTextureCreateInfo* const createInfo,
@@ -17,14 +19,24 @@ KTX_error_code ktxTexture_SetImageFromMemoryWrapped(
     ktx_uint32_t level,
     ktx_uint32_t layer,
     ktx_uint32_t faceSlice,
-    unsigned char* file_data,
+    const unsigned char* file_data,
     ktx_size_t image_size
 ) {
     return ktxTexture_SetImageFromMemory((ktxTexture*)texture, level, layer, faceSlice, file_data, image_size);
 }
 
-KTX_error_code ktxTexture_WriteToNamedFileWrapped(ktxTexture2* texture, const char* filename) {
-    return ktxTexture_WriteToNamedFile((ktxTexture*)texture, filename);
+ktx_uint8_t* ktxTexture_WriteToMemoryWrapped(
+    ktxTexture2* texture,
+    ktx_size_t* size,
+    ktx_error_code_e* return_error_code
+) {
+    ktx_uint8_t* bytes = nullptr;
+    *return_error_code = ktxTexture_WriteToMemory((ktxTexture*)texture, &bytes, size);
+    return bytes;
+}
+
+void ktxFreeMemoryWrapped(ktx_uint8_t* bytes) {
+    free(bytes);
 }
 
 void ktxTexture_DestroyWrapped(ktxTexture2* texture) {
@@ -39,51 +51,43 @@ KTX_error_code ktxTexture2_CompressAstcExWrapped(ktxTexture2* texture, std::uniq
 #pragma GCC diagnostic push
 #pragma GCC diagnostic ignored "-Wmissing-field-initializers"
 
-ktxBasisParams intoCPPBasisUastcParams(const KtxBasisParams* basisParams, const KtxBasisUASTCParams* uastcParams) {
-    ktxBasisParams output = { .uastc = basisParams->uastc };
-    output.structSize = sizeof(output);
-    if (basisParams->verbose) {
-        output.verbose = *basisParams->verbose;
-    }
-    if (basisParams->no_sse) {
-        output.noSSE = *basisParams->no_sse;
-    }
-    if (basisParams->thread_count) {
-        output.threadCount = *basisParams->thread_count;
+template <typename Optional, typename Value>
+static void setIfPresent(const Optional& optional, Value& output) {
+    if (optional.is_set) {
+        output = optional.value;
     }
-    if (uastcParams->uastc_flags) {
-        output.uastcFlags = uastcParams->uastc_flags->value;
-    }
-    if (uastcParams->uastc_rdo) {
-        output.uastcRDO = *uastcParams->uastc_rdo;
-    }
-    if (uastcParams->uastc_rdo_quality_scalar) {
-        output.uastcRDOQualityScalar = *uastcParams->uastc_rdo_quality_scalar;
-    }
-    if (uastcParams->uastc_rdo_dict_size) {
-        output.uastcRDODictSize = *uastcParams->uastc_rdo_dict_size;
-    }
-    if (uastcParams->uastc_rdo_max_smooth_block_error_scale) {
-        output.uastcRDOMaxSmoothBlockErrorScale = *uastcParams->uastc_rdo_max_smooth_block_error_scale;
-    }
-    if (uastcParams->uastc_rdo_max_smooth_block_std_dev) {
-        output.uastcRDOMaxSmoothBlockStdDev = *uastcParams->uastc_rdo_max_smooth_block_std_dev;
-    }
-    if (uastcParams->uastc_rdo_dont_favor_simpler_modes) {
-        output.uastcRDODontFavorSimplerModes = *uastcParams->uastc_rdo_dont_favor_simpler_modes;
-    }
-    if (uastcParams->uastc_rdo_no_multithreading) {
-        output.uastcRDONoMultithreading = *uastcParams->uastc_rdo_no_multithreading;
-    }
-    if (uastcParams->input_swizzle && uastcParams->input_swizzle->length() > 4) {
-        output.inputSwizzle[0] = uastcParams->input_swizzle->data()[0];
-        output.inputSwizzle[1] = uastcParams->input_swizzle->data()[1];
-        output.inputSwizzle[2] = uastcParams->input_swizzle->data()[2];
-        output.inputSwizzle[3] = uastcParams->input_swizzle->data()[3];
-    }
-    if (uastcParams->pre_swizzle) {
-        output.preSwizzle = uastcParams->pre_swizzle;
+}
+
+static void setInputSwizzle(const rust::String& input_swizzle, char (&output)[4]) {
+    if (input_swizzle.length() == 4) {
+        output[0] = input_swizzle.data()[0];
+        output[1] = input_swizzle.data()[1];
+        output[2] = input_swizzle.data()[2];
+        output[3] = input_swizzle.data()[3];
     }
+}
+
+static ktxBasisParams intoCPPBasisParams(const KtxBasisParams* basisParams) {
+    ktxBasisParams output = { .uastc = basisParams->uastc };
+    output.structSize = sizeof(output);
+    setIfPresent(basisParams->verbose, output.verbose);
+    setIfPresent(basisParams->no_sse, output.noSSE);
+    setIfPresent(basisParams->thread_count, output.threadCount);
+    return output;
+}
+
+ktxBasisParams intoCPPBasisUastcParams(const KtxBasisParams* basisParams, const KtxBasisUASTCParams* uastcParams) {
+    ktxBasisParams output = intoCPPBasisParams(basisParams);
+    setIfPresent(uastcParams->uastc_flags, output.uastcFlags);
+    setIfPresent(uastcParams->uastc_rdo, output.uastcRDO);
+    setIfPresent(uastcParams->uastc_rdo_quality_scalar, output.uastcRDOQualityScalar);
+    setIfPresent(uastcParams->uastc_rdo_dict_size, output.uastcRDODictSize);
+    setIfPresent(uastcParams->uastc_rdo_max_smooth_block_error_scale, output.uastcRDOMaxSmoothBlockErrorScale);
+    setIfPresent(uastcParams->uastc_rdo_max_smooth_block_std_dev, output.uastcRDOMaxSmoothBlockStdDev);
+    setIfPresent(uastcParams->uastc_rdo_dont_favor_simpler_modes, output.uastcRDODontFavorSimplerModes);
+    setIfPresent(uastcParams->uastc_rdo_no_multithreading, output.uastcRDONoMultithreading);
+    setInputSwizzle(uastcParams->input_swizzle, output.inputSwizzle);
+    setIfPresent(uastcParams->pre_swizzle, output.preSwizzle);
     return output;
 }
 
@@ -93,57 +97,19 @@ KTX_error_code ktxTexture2_CompressBasisUastc(ktxTexture2* texture, const KtxBas
 }
 
 ktxBasisParams intoCPPBasisEtc1sParams(const KtxBasisParams* basisParams, const KtxBasisETC1SParams* etc1sParams) {
-    ktxBasisParams output = { .uastc = basisParams->uastc };
-    output.structSize = sizeof(output);
-    if (basisParams->verbose) {
-        output.verbose = *basisParams->verbose;
-    }
-    if (basisParams->no_sse) {
-        output.noSSE = *basisParams->no_sse;
-    }
-    if (basisParams->thread_count) {
-        output.threadCount = *basisParams->thread_count;
-    }
-    if (etc1sParams->compression_level) {
-        output.compressionLevel = *etc1sParams->compression_level;
-    }
-    if (etc1sParams->quality_level) {
-        output.qualityLevel = *etc1sParams->quality_level;
-    }
-    if (etc1sParams->max_endpoints) {
-        output.maxEndpoints = *etc1sParams->max_endpoints;
-    }
-    if (etc1sParams->endpoint_rdo_threshold) {
-        output.endpointRDOThreshold = *etc1sParams->endpoint_rdo_threshold;
-    }
-    if (etc1sParams->max_selectors) {
-        output.maxSelectors = *etc1sParams->max_selectors;
-    }
-    if (etc1sParams->selector_rdo_threshold) {
-        output.selectorRDOThreshold = *etc1sParams->selector_rdo_threshold;
-    }
-    if (etc1sParams->input_swizzle && etc1sParams->input_swizzle->length() > 4) {
-        output.inputSwizzle[0] = etc1sParams->input_swizzle->data()[0];
-        output.inputSwizzle[1] = etc1sParams->input_swizzle->data()[1];
-        output.inputSwizzle[2] = etc1sParams->input_swizzle->data()[2];
-        output.inputSwizzle[3] = etc1sParams->input_swizzle->data()[3];
-    }
-    if (etc1sParams->normal_map) {
-        output.normalMap = *etc1sParams->normal_map;
-    }
-    if (etc1sParams->separate_rgt_to_rgba) {
-        output.separateRGToRGB_A = *etc1sParams->separate_rgt_to_rgba;
-    }
-    if (etc1sParams->pre_swizzle) {
-        output.preSwizzle = *etc1sParams->pre_swizzle;
-    }
-    if (etc1sParams->no_endpoint_rdo) {
-        output.noEndpointRDO = *etc1sParams->no_selector_rdo;
-    }
-    if (etc1sParams->no_selector_rdo) {
-        output.noSelectorRDO = *etc1sParams->no_selector_rdo;
-    }
-
+    ktxBasisParams output = intoCPPBasisParams(basisParams);
+    setIfPresent(etc1sParams->compression_level, output.compressionLevel);
+    setIfPresent(etc1sParams->quality_level, output.qualityLevel);
+    setIfPresent(etc1sParams->max_endpoints, output.maxEndpoints);
+    setIfPresent(etc1sParams->endpoint_rdo_threshold, output.endpointRDOThreshold);
+    setIfPresent(etc1sParams->max_selectors, output.maxSelectors);
+    setIfPresent(etc1sParams->selector_rdo_threshold, output.selectorRDOThreshold);
+    setInputSwizzle(etc1sParams->input_swizzle, output.inputSwizzle);
+    setIfPresent(etc1sParams->normal_map, output.normalMap);
+    setIfPresent(etc1sParams->separate_rgt_to_rgba, output.separateRGToRGB_A);
+    setIfPresent(etc1sParams->pre_swizzle, output.preSwizzle);
+    setIfPresent(etc1sParams->no_endpoint_rdo, output.noEndpointRDO);
+    setIfPresent(etc1sParams->no_selector_rdo, output.noSelectorRDO);
     return output;
 }
 
@@ -153,3 +119,103 @@ KTX_error_code ktxTexture2_CompressBasisEtc1s(ktxTexture2* texture, const KtxBas
     ktxBasisParams params = intoCPPBasisEtc1sParams(basisParams, etc1sParams);
     return ktxTexture2_CompressBasisEx(texture, &params);
 }
+
+ktxTexture2*
+ktxTexture2_CreateFromMemoryWrapped(
+    const ktx_uint8_t* bytes,
+    ktx_size_t size,
+    ktx_error_code_e* return_error_code
+) {
+    ktxTexture2* ktx_texture = nullptr;
+    *return_error_code = ktxTexture2_CreateFromMemory(bytes, size, KTX_TEXTURE_CREATE_LOAD_IMAGE_DATA_BIT, &ktx_texture);
+    return ktx_texture;
+}
+
+void ktxTexture2_GetInfoWrapped(ktxTexture2* texture, KtxTextureInfo* info) {
+    info->vk_format = texture->vkFormat;
+    info->base_width = texture->baseWidth;
+    info->base_height = texture->baseHeight;
+    info->base_depth = texture->baseDepth;
+    info->num_dimensions = texture->numDimensions;
+    info->num_levels = texture->numLevels;
+    info->num_layers = texture->numLayers;
+    info->num_faces = texture->numFaces;
+    info->is_array = texture->isArray;
+    info->is_cubemap = texture->isCubemap;
+    info->is_compressed = texture->isCompressed;
+    info->supercompression_scheme = texture->supercompressionScheme;
+    info->data_size = texture->dataSize;
+}
+
+const ktx_uint8_t* ktxTexture2_GetImageWrapped(
+    ktxTexture2* texture,
+    ktx_uint32_t level,
+    ktx_uint32_t layer,
+    ktx_uint32_t faceSlice,
+    ktx_size_t* image_size,
+    ktx_error_code_e* return_error_code
+) {
+    if (ktxTexture2_NeedsTranscoding(texture) || texture->pData == nullptr) {
+        *return_error_code = KTX_INVALID_OPERATION;
+        return nullptr;
+    }
+    ktx_size_t offset = 0;
+    *return_error_code = ktxTexture_GetImageOffset((ktxTexture*)texture, level, layer, faceSlice, &offset);
+    if (*return_error_code != KTX_SUCCESS) {
+        return nullptr;
+    }
+    *image_size = ktxTexture_GetImageSize((ktxTexture*)texture, level);
+    return ktxTexture_GetData((ktxTexture*)texture) + offset;
+}
+
+ktx_uint32_t* ktxTexture2_GetDfdWrapped(ktxTexture2* texture) {
+    return texture->pDfd;
+}
+
+ktx_uint32_t ktxTexture2_GetKVCountWrapped(ktxTexture2* texture) {
+    ktx_uint32_t count = 0;
+    for (ktxHashListEntry* entry = texture->kvDataHead; entry != nullptr; entry = ktxHashList_Next(entry)) {
+        count++;
+    }
+    return count;
+}
+
+static ktxHashListEntry* ktxTexture2_GetKVEntry(ktxTexture2* texture, ktx_uint32_t index) {
+    ktxHashListEntry* entry = texture->kvDataHead;
+    for (ktx_uint32_t i = 0; entry != nullptr && i < index; i++) {
+        entry = ktxHashList_Next(entry);
+    }
+    return entry;
+}
+
+const char* ktxTexture2_GetKVKeyWrapped(ktxTexture2* texture, ktx_uint32_t index) {
+    ktxHashListEntry* entry = ktxTexture2_GetKVEntry(texture, index);
+    if (entry == nullptr) {
+        return nullptr;
+    }
+    ktx_uint32_t key_length = 0;
+    char* key = nullptr;
+    ktxHashListEntry_GetKey(entry, &key_length, &key);
+    return key;
+}
+
+const ktx_uint8_t* ktxTexture2_GetKVValueWrapped(ktxTexture2* texture, ktx_uint32_t index, ktx_uint32_t* value_length) {
+    ktxHashListEntry* entry = ktxTexture2_GetKVEntry(texture, index);
+    if (entry == nullptr) {
+        *value_length = 0;
+        return nullptr;
+    }
+    void* value = nullptr;
+    ktxHashListEntry_GetValue(entry, value_length, &value);
+    return (const ktx_uint8_t*)value;
+}
+
+KTX_error_code ktxTexture2_SetKVWrapped(ktxTexture2* texture, const char* key, ktx_uint32_t value_length, const ktx_uint8_t* value) {
+    // AddKVPair keeps duplicate keys, so remove any previous value first
+    ktxHashList_DeleteKVPair(&texture->kvDataHead, key);
+    return ktxHashList_AddKVPair(&texture->kvDataHead, key, value_length, value);
+}
+
+KTX_error_code ktxTexture2_DeleteKVWrapped(ktxTexture2* texture, const char* key) {
+    return ktxHashList_DeleteKVPair(&texture->kvDataHead, key);
+}
@@ -6,6 +6,7 @@
 struct KtxBasisParams;
 struct KtxBasisUASTCParams;
 struct KtxBasisETC1SParams;
+struct KtxTextureInfo;
 
 ktxTexture2*
 ktxTexture2_CreateWrapped(
@@ -19,11 +20,17 @@ KTX_error_code ktxTexture_SetImageFromMemoryWrapped(
     ktx_uint32_t level,
     ktx_uint32_t layer,
     ktx_uint32_t faceSlice,
-    unsigned char* file_data,
+    const unsigned char* file_data,
     ktx_size_t image_size
 );
 
-KTX_error_code ktxTexture_WriteToNamedFileWrapped(ktxTexture2* texture, const char* filename);
+ktx_uint8_t* ktxTexture_WriteToMemoryWrapped(
+    ktxTexture2* texture,
+    ktx_size_t* size,
+    ktx_error_code_e* return_error_code
+);
+
+void ktxFreeMemoryWrapped(ktx_uint8_t* bytes);
 
 void ktxTexture_DestroyWrapped(ktxTexture2* texture);
 
@@ -36,3 +43,33 @@ KTX_error_code ktxTexture2_CompressBasisUastc(ktxTexture2* texture, const KtxBas
 ktxBasisParams intoCPPBasisEtc1sParams(const KtxBasisParams* basisParams, const KtxBasisETC1SParams* etc1sParams);
 
 KTX_error_code ktxTexture2_CompressBasisEtc1s(ktxTexture2* texture, const KtxBasisParams* basisParams, const KtxBasisETC1SParams* etc1sParams);
+
+ktxTexture2*
+ktxTexture2_CreateFromMemoryWrapped(
+    const ktx_uint8_t* bytes,
+    ktx_size_t size,
+    ktx_error_code_e* return_error_code
+);
+
+void ktxTexture2_GetInfoWrapped(ktxTexture2* texture, KtxTextureInfo* info);
+
+const ktx_uint8_t* ktxTexture2_GetImageWrapped(
+    ktxTexture2* texture,
+    ktx_uint32_t level,
+    ktx_uint32_t layer,
+    ktx_uint32_t faceSlice,
+    ktx_size_t* image_size,
+    ktx_error_code_e* return_error_code
+);
+
+ktx_uint32_t* ktxTexture2_GetDfdWrapped(ktxTexture2* texture);
+
+ktx_uint32_t ktxTexture2_GetKVCountWrapped(ktxTexture2* texture);
+
+const char* ktxTexture2_GetKVKeyWrapped(ktxTexture2* texture, ktx_uint32_t index);
+
+const ktx_uint8_t* ktxTexture2_GetKVValueWrapped(ktxTexture2* texture, ktx_uint32_t index, ktx_uint32_t* value_length);
+
+KTX_error_code ktxTexture2_SetKVWrapped(ktxTexture2* texture, const char* key, ktx_uint32_t value_length, const ktx_uint8_t* value);
+
+KTX_error_code ktxTexture2_DeleteKVWrapped(ktxTexture2* texture, const char* key);
@@ -1,28 +1,62 @@
 #![allow(unsafe_code)]
 
-use std::{fs::read, path::Path};
+#[cfg(feature = "libktx")]
+use std::{
+    fs::{copy, write},
+    path::{Path, PathBuf},
+};
 
+#[cfg(feature = "libktx")]
 use autocxx::prelude::*;
+#[cfg(feature = "libktx")]
 use config::CompressionConfig;
-use ktx_texture::KtxTexture;
-use shared_types::{LocalError, SupportedImages};
-use zune_core::{colorspace::ColorSpace, options::DecoderOptions};
-use zune_jpeg::JpegDecoder;
-use zune_png::PngDecoder;
+#[cfg(feature = "libktx")]
+use error::{KtxError, KtxStage};
+#[cfg(feature = "libktx")]
+use ktx_texture::{ImageSetInMemory, KtxTexture, TextureShape};
+#[cfg(feature = "libktx")]
+use ktx_types::TranscodeTarget;
+#[cfg(feature = "libktx")]
+use metadata::{MetadataValue, PREMULTIPLIED_KEY, SOURCE_PATH_KEY};
+#[cfg(feature = "libktx")]
+use mipmaps::generate_source_mip_chain;
+#[cfg(feature = "libktx")]
+use shared_types::SupportedImages;
+#[cfg(feature = "libktx")]
+use source_image::{Channels, SourceImage};
+#[cfg(feature = "libktx")]
+use verify::measure_quality;
 
+pub mod alpha;
+pub mod color_space;
+#[cfg(feature = "libktx")]
 pub mod config;
+pub mod deflation;
+pub mod dfd;
+pub mod error;
+#[cfg(feature = "libktx")]
 pub mod ktx_texture;
+#[cfg(feature = "libktx")]
 pub mod ktx_types;
+pub mod metadata;
+pub mod mipmaps;
+pub mod source_image;
+pub mod texture_shape;
+pub mod verify;
+#[cfg(feature = "rust-writer")]
+pub mod writer;
 
+#[cfg(feature = "libktx")]
 include_cpp! {
     #include "ktx.h"
-    #include "vk_format.h"
+    #include "ktx_formats.h"
     #include "ktx_wrappers.h"
     safety!(unsafe)
     extern_cpp_type!("KtxBasisParams", crate::ffi2::KtxBasisParams)
     extern_cpp_type!("KtxPackUastcFlags", crate::ffi2::KtxPackUastcFlags)
     extern_cpp_type!("KtxBasisETC1SParams", crate::ffi2::KtxBasisETC1SParams)
     extern_cpp_type!("KtxBasisUASTCParams", crate::ffi2::KtxBasisUASTCParams)
+    extern_cpp_type!("KtxTextureInfo", crate::ffi2::KtxTextureInfo)
     // What types and functions we want to generate
     generate!("ktxTexture2")
     generate_pod!("ktx_error_code_e")
@@ -32,6 +66,7 @@ include_cpp! {
     generate_pod!("ktxTextureCreateInfo")
     generate_pod!("VkFormat")
     generate_pod!("ktxAstcParams")
+    generate_pod!("ktx_transcode_fmt_e")
     generate!("ktxTextureCreateStorageEnum")
     generate!("ktxTexture2_CreateWrapped")
     generate!("ktxTexture2_CompressAstcExWrapped")
@@ -40,10 +75,23 @@ include_cpp! {
     generate!("ktxTexture2_DeflateZstd")
     generate!("ktxTexture2_DeflateZLIB")
     generate!("ktxTexture_SetImageFromMemoryWrapped")
-    generate!("ktxTexture_WriteToNamedFileWrapped")
+    generate!("ktxTexture_WriteToMemoryWrapped")
+    generate!("ktxFreeMemoryWrapped")
     generate!("ktxTexture_DestroyWrapped")
+    generate!("ktxTexture2_CreateFromMemoryWrapped")
+    generate!("ktxTexture2_GetInfoWrapped")
+    generate!("ktxTexture2_GetImageWrapped")
+    generate!("ktxTexture2_GetDfdWrapped")
+    generate!("ktxTexture2_GetKVCountWrapped")
+    generate!("ktxTexture2_GetKVKeyWrapped")
+    generate!("ktxTexture2_GetKVValueWrapped")
+    generate!("ktxTexture2_SetKVWrapped")
+    generate!("ktxTexture2_DeleteKVWrapped")
+    generate!("ktxTexture2_NeedsTranscoding")
+    generate!("ktxTexture2_TranscodeBasis")
 }
 
+#[cfg(feature = "libktx")]
 #[cxx::bridge]
 pub mod ffi2 {
     #[derive(Copy, Clone, Debug, PartialEq)]
@@ -51,170 +99,463 @@ pub mod ffi2 {
         value: u32,
     }
 
-    #[derive(Debug)]
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    pub struct OptionalU32 {
+        pub is_set: bool,
+        pub value: u32,
+    }
+
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    pub struct OptionalF32 {
+        pub is_set: bool,
+        pub value: f32,
+    }
+
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    pub struct OptionalBool {
+        pub is_set: bool,
+        pub value: bool,
+    }
+
+    #[derive(Clone, Debug, Default)]
     pub struct KtxBasisETC1SParams {
-        pub compression_level: *const u32,
-        pub quality_level: *const u32,
-        pub max_endpoints: *const u32,
-        pub endpoint_rdo_threshold: *const f32,
-        pub max_selectors: *const u32,
-        pub selector_rdo_threshold: *const f32,
-        pub input_swizzle: *const String,
-        pub normal_map: *const bool,
-        pub separate_rgt_to_rgba: *const bool,
-        pub pre_swizzle: *const bool,
-        pub no_endpoint_rdo: *const bool,
-        pub no_selector_rdo: *const bool,
-    }
-
-    #[derive(Debug)]
+        pub compression_level: OptionalU32,
+        pub quality_level: OptionalU32,
+        pub max_endpoints: OptionalU32,
+        pub endpoint_rdo_threshold: OptionalF32,
+        pub max_selectors: OptionalU32,
+        pub selector_rdo_threshold: OptionalF32,
+        /// Empty when unset
+        pub input_swizzle: String,
+        pub normal_map: OptionalBool,
+        pub separate_rgt_to_rgba: OptionalBool,
+        pub pre_swizzle: OptionalBool,
+        pub no_endpoint_rdo: OptionalBool,
+        pub no_selector_rdo: OptionalBool,
+    }
+
+    #[derive(Clone, Debug, Default)]
     pub struct KtxBasisUASTCParams {
-        pub uastc_flags: *const KtxPackUastcFlags,
-        pub uastc_rdo: *const bool,
-        pub uastc_rdo_quality_scalar: *const f32,
-        pub uastc_rdo_dict_size: *const u32,
-        pub uastc_rdo_max_smooth_block_error_scale: *const f32,
-        pub uastc_rdo_max_smooth_block_std_dev: *const f32,
-        pub uastc_rdo_dont_favor_simpler_modes: *const bool,
-        pub uastc_rdo_no_multithreading: *const bool,
-        pub input_swizzle: *const String,
-        pub pre_swizzle: *const bool,
-    }
-
-    #[derive(Debug)]
+        pub uastc_flags: OptionalU32,
+        pub uastc_rdo: OptionalBool,
+        pub uastc_rdo_quality_scalar: OptionalF32,
+        pub uastc_rdo_dict_size: OptionalU32,
+        pub uastc_rdo_max_smooth_block_error_scale: OptionalF32,
+        pub uastc_rdo_max_smooth_block_std_dev: OptionalF32,
+        pub uastc_rdo_dont_favor_simpler_modes: OptionalBool,
+        pub uastc_rdo_no_multithreading: OptionalBool,
+        /// Empty when unset
+        pub input_swizzle: String,
+        pub pre_swizzle: OptionalBool,
+    }
+
+    #[derive(Clone, Copy, Debug, Default)]
+    pub struct KtxTextureInfo {
+        pub vk_format: u32,
+        pub base_width: u32,
+        pub base_height: u32,
+        pub base_depth: u32,
+        pub num_dimensions: u32,
+        pub num_levels: u32,
+        pub num_layers: u32,
+        pub num_faces: u32,
+        pub is_array: bool,
+        pub is_cubemap: bool,
+        pub is_compressed: bool,
+        pub supercompression_scheme: u32,
+        pub data_size: usize,
+    }
+
+    #[derive(Clone, Copy, Debug, Default)]
     pub struct KtxBasisParams {
-        pub struct_size: *const u32,
         pub uastc: bool,
-        pub verbose: *const bool,
-        pub no_sse: *const bool,
-        pub thread_count: *const u32,
+        pub verbose: OptionalBool,
+        pub no_sse: OptionalBool,
+        pub thread_count: OptionalU32,
     }
 }
 
-fn premultiply_alpha(mut image_data: Vec<u8>) -> Result<Vec<u8>, LocalError> {
-    if image_data.len() % 4 != 0 {
-        return Err("Image does not appear to be rgba, cannot premultiply".into());
-    }
-    for pixel in image_data.chunks_mut(4) {
-        pixel[0] = ((pixel[0] as u32 * pixel[3] as u32) / 255) as u8;
-        pixel[1] = ((pixel[1] as u32 * pixel[3] as u32) / 255) as u8;
-        pixel[2] = ((pixel[2] as u32 * pixel[3] as u32) / 255) as u8;
+#[cfg(feature = "libktx")]
+fn decode_source_image(
+    image_input_path: &Path,
+    image_type: &SupportedImages,
+    channels: Channels,
+) -> Result<SourceImage, KtxError> {
+    SourceImage::open(image_input_path, image_type, channels).map_err(|source| KtxError::Decode {
+        path: Some(image_input_path.to_path_buf()),
+        source,
+    })
+}
+
+/// Checks that `config` can encode `source_image` and premultiplies its alpha if the config asks
+/// for it
+#[cfg(feature = "libktx")]
+fn prepare_source_image(
+    source_image: &mut SourceImage,
+    config: &CompressionConfig,
+) -> Result<(), KtxError> {
+    config
+        .validate_for(source_image.pixel_format)
+        .map_err(|err| KtxError::invalid_config(err.to_string()))?;
+    if config.premultiply.unwrap_or(true) {
+        let color_space = source_image
+            .pixel_format
+            .effective_color_space(config.color_space);
+        source_image.premultiply_alpha(color_space);
     }
-    Ok(image_data)
+    Ok(())
 }
 
-fn extract_png_data(
-    png_path: &Path,
-    should_premultiply: bool,
-) -> Result<(u32, u32, Vec<u8>), LocalError> {
-    let png_file_contents = read(png_path)?;
-
-    let decoder_options = DecoderOptions::default()
-        .png_set_strip_to_8bit(true)
-        .png_set_add_alpha_channel(true);
-    let mut png_decoder =
-        PngDecoder::new_with_options(png_file_contents.as_slice(), decoder_options);
-    let png_data = png_decoder.decode_raw()?;
-    let png_data = if should_premultiply {
-        premultiply_alpha(png_data)?
-    } else {
-        png_data
-    };
-    let (width, height) = png_decoder.get_dimensions().ok_or(format!(
-        "Cannot get dimensions for png: {}",
-        png_path.display()
-    ))?;
-    Ok((width as u32, height as u32, png_data))
+/// Encodes a 2D texture from pixels already in memory and returns the KTX2 file. Mipmaps,
+/// premultiplication, color space, compression and metadata all follow `config`
+#[cfg(feature = "libktx")]
+pub fn encode_texture(
+    source_image: SourceImage,
+    config: &CompressionConfig,
+) -> Result<Vec<u8>, KtxError> {
+    encode_source_images(vec![source_image], TextureShape::Texture2D, config, &[])
 }
 
-fn extract_jpeg_data(
-    jpeg_path: &Path,
-    should_premultiply: bool,
-) -> Result<(u32, u32, Vec<u8>), LocalError> {
-    let jpeg_file_contents = read(jpeg_path)?;
-
-    let decoder_options = DecoderOptions::default().jpeg_set_out_colorspace(ColorSpace::RGBA);
-    let mut jpeg_decoder =
-        JpegDecoder::new_with_options(jpeg_file_contents.as_slice(), decoder_options);
-    let jpeg_data = jpeg_decoder.decode()?;
-    let jpeg_data = if should_premultiply {
-        premultiply_alpha(jpeg_data)?
-    } else {
-        jpeg_data
-    };
-    let (width, height) = jpeg_decoder
-        .dimensions()
-        .ok_or(LocalError::from("JPEG decoding failed"))?;
-    Ok((width as u32, height as u32, jpeg_data))
+/// Encodes a cubemap, array or volume texture from pixels already in memory and returns the KTX2
+/// file. The images are ordered layer by layer, and within a layer face by face
+/// (+X, -X, +Y, -Y, +Z, -Z) or depth slice by depth slice. Every image must have the same
+/// dimensions and pixel format
+#[cfg(feature = "libktx")]
+pub fn encode_texture_with_shape(
+    source_images: Vec<SourceImage>,
+    shape: TextureShape,
+    config: &CompressionConfig,
+) -> Result<Vec<u8>, KtxError> {
+    encode_source_images(source_images, shape, config, &[])
 }
 
+#[cfg(feature = "libktx")]
 pub fn write_texture_from_image(
     image_input_path: &Path,
     image_write_path: Option<&Path>,
     config: &CompressionConfig,
     image_type: &SupportedImages,
-) -> Result<(), LocalError> {
-    let should_premultiply = config.premultiply.unwrap_or(true);
-    let (width, height, mut image_data) = match image_type {
-        SupportedImages::Png => extract_png_data(image_input_path, should_premultiply)?,
-        SupportedImages::Jpeg => extract_jpeg_data(image_input_path, should_premultiply)?,
+) -> Result<(), KtxError> {
+    let source_image = decode_source_image(image_input_path, image_type, config.channels)?;
+    let ktx_bytes = encode_source_images(
+        vec![source_image],
+        TextureShape::Texture2D,
+        config,
+        &[image_input_path],
+    )
+    .map_err(|err| err.with_path(image_input_path))?;
+
+    let canonicalized_path = if let Some(image_write_path) = image_write_path {
+        canonicalize_write_path(image_write_path)?
+    } else {
+        image_input_path
+            .canonicalize()
+            .map_err(|err| KtxError::io(KtxStage::Write, image_input_path, err))?
     };
+    write_ktx_file(ktx_bytes, canonicalized_path, image_input_path)
+}
+
+/// Builds a cubemap, array or volume texture out of several source images, ordered as described
+/// by `encode_texture_with_shape`
+#[cfg(feature = "libktx")]
+pub fn write_texture_from_images(
+    image_input_paths: &[&Path],
+    image_write_path: &Path,
+    shape: TextureShape,
+    config: &CompressionConfig,
+) -> Result<(), KtxError> {
+    let source_images = image_input_paths
+        .iter()
+        .map(|image_input_path| {
+            let image_type = SupportedImages::try_from(*image_input_path).map_err(|source| {
+                KtxError::Decode {
+                    path: Some(image_input_path.to_path_buf()),
+                    source,
+                }
+            })?;
+            decode_source_image(image_input_path, &image_type, config.channels)
+        })
+        .collect::<Result<Vec<_>, KtxError>>()?;
+    let ktx_bytes = encode_source_images(source_images, shape, config, image_input_paths)
+        .map_err(|err| err.with_path(image_write_path))?;
+
+    let canonicalized_path = canonicalize_write_path(image_write_path)?;
+    write_ktx_file(ktx_bytes, canonicalized_path, image_write_path)
+}
+
+/// Transcodes the Basis Universal texture at `input_path` to the GPU format `target` and writes
+/// it to `output_path`, which may be `input_path` to transcode in place. A texture already in a
+/// GPU format, such as one transcoded in place by an earlier run, is copied to `output_path`
+/// unchanged and `false` is returned, so an output directory always gets every texture
+#[cfg(feature = "libktx")]
+pub fn transcode_file(
+    input_path: &Path,
+    target: TranscodeTarget,
+    output_path: &Path,
+) -> Result<bool, KtxError> {
+    let mut ktx_texture = KtxTexture::open(input_path)?;
+    if !ktx_texture.needs_transcoding() {
+        if output_path != input_path {
+            copy(input_path, output_path)
+                .map_err(|err| KtxError::io(KtxStage::Write, output_path, err))?;
+        }
+        return Ok(false);
+    }
+    // libktx rebuilds the data format descriptor for the new format, dropping its flags
+    let alpha_premultiplied = ktx_texture.alpha_premultiplied();
+    ktx_texture
+        .transcode(target)
+        .map_err(|err| err.with_path(input_path))?;
+    ktx_texture
+        .set_alpha_premultiplied(alpha_premultiplied)
+        .map_err(|err| err.with_path(input_path))?;
 
-    let ktx_texture = KtxTexture::new(width, height, ffi::VkFormat::VK_FORMAT_R8G8B8A8_UNORM)
-        .map_err(|result| {
-            LocalError::from(format!(
-                "Error creating ktxTexture for {} when writing kxt, :{}",
-                image_input_path.display(),
-                String::from(result)
-            ))
-        })?;
-
-    let mut ktx_texture = unsafe {
-        ktx_texture
-            .set_image_in_memory(image_data.as_mut_ptr())
-            .map_err(|result| {
-                LocalError::from(format!(
-                    "Error setting image for {} from memory: {}",
-                    image_input_path.display(),
-                    String::from(result)
-                ))
-            })?
+    let ktx_bytes = ktx_texture
+        .write_to_vec()
+        .map_err(|err| err.with_path(output_path))?;
+    write(output_path, ktx_bytes).map_err(|err| KtxError::io(KtxStage::Write, output_path, err))?;
+    Ok(true)
+}
+
+/// Shared by the in-memory and path based entry points. `image_input_paths` is only used to name
+/// the failing image in errors and for the `SourcePath` metadata, and may be empty
+#[cfg(feature = "libktx")]
+fn encode_source_images(
+    mut source_images: Vec<SourceImage>,
+    shape: TextureShape,
+    config: &CompressionConfig,
+    image_input_paths: &[&Path],
+) -> Result<Vec<u8>, KtxError> {
+    let with_image_path = |image_index: usize| {
+        let image_input_path = image_input_paths.get(image_index).copied();
+        move |err: KtxError| match image_input_path {
+            Some(image_input_path) => err.with_path(image_input_path),
+            None => err,
+        }
     };
 
-    ktx_texture.set_compression(config).map_err(|result| {
-        LocalError::from(format!(
-            "Error settings compression type {:?}: {}",
-            config.config_type(),
-            String::from(result)
-        ))
-    })?;
-
-    let mut canonicalized_path = if let Some(image_write_path) = image_write_path {
-        image_write_path
-            .parent()
-            .unwrap()
-            .canonicalize()?
-            .join(image_write_path.file_name().unwrap())
-    } else {
-        image_input_path.canonicalize()?
+    config
+        .validate()
+        .map_err(|err| KtxError::invalid_config(err.to_string()))?;
+    if source_images.len() != shape.image_count() as usize {
+        return Err(KtxError::invalid_config(format!(
+            "{shape:?} needs {} images but {} were given",
+            shape.image_count(),
+            source_images.len()
+        )));
+    }
+    let mipmap_config = config
+        .mipmaps
+        .as_ref()
+        .filter(|mipmap_config| mipmap_config.enabled);
+    if mipmap_config.is_some() && matches!(shape, TextureShape::Volume { .. }) {
+        return Err(KtxError::invalid_config(
+            "Mipmap generation is not supported for volume textures",
+        ));
+    }
+
+    let (width, height, pixel_format) = (
+        source_images[0].width,
+        source_images[0].height,
+        source_images[0].pixel_format,
+    );
+    for (image_index, source_image) in source_images.iter_mut().enumerate() {
+        if (source_image.width, source_image.height) != (width, height) {
+            return Err(with_image_path(image_index)(KtxError::invalid_config(
+                format!(
+                    "Image {image_index} is {}x{} but the first image is {width}x{height}",
+                    source_image.width, source_image.height
+                ),
+            )));
+        }
+        if source_image.pixel_format != pixel_format {
+            return Err(with_image_path(image_index)(KtxError::invalid_config(
+                format!(
+                    "Image {image_index} is {:?} but the first image is {pixel_format:?}",
+                    source_image.pixel_format
+                ),
+            )));
+        }
+        prepare_source_image(source_image, config).map_err(with_image_path(image_index))?;
+    }
+    let color_space = pixel_format.effective_color_space(config.color_space);
+
+    let num_levels =
+        mipmap_config.map_or(1, |mipmap_config| mipmap_config.level_count(width, height));
+    let mut ktx_texture = KtxTexture::new_with_shape(
+        width,
+        height,
+        num_levels,
+        shape,
+        pixel_format.vk_format(color_space),
+    )?;
+    ktx_texture.set_color_space(color_space)?;
+
+    let slices_per_layer = shape.num_faces() * shape.depth();
+    for (image_index, source_image) in source_images.iter().enumerate() {
+        let layer = image_index as u32 / slices_per_layer;
+        let face_slice = image_index as u32 % slices_per_layer;
+        let mip_levels = match mipmap_config {
+            Some(mipmap_config) => {
+                generate_source_mip_chain(source_image, mipmap_config, color_space)
+            }
+            None => vec![],
+        };
+        let levels = std::iter::once(&source_image.data).chain(mip_levels.iter());
+        for (level, level_data) in levels.enumerate() {
+            ktx_texture
+                .set_image(level as u32, layer, face_slice, level_data)
+                .map_err(with_image_path(image_index))?;
+        }
+    }
+
+    // Without an alpha channel there is nothing premultiplied, whatever the config asks for
+    let alpha_premultiplied = config.premultiply.unwrap_or(true) && pixel_format.has_alpha();
+    let mut ktx_texture = ktx_texture.finish_setting_images()?;
+    apply_metadata(
+        &mut ktx_texture,
+        config,
+        image_input_paths,
+        alpha_premultiplied,
+    )?;
+    ktx_texture.set_compression(config)?;
+    ktx_texture.set_alpha_premultiplied(alpha_premultiplied)?;
+    let ktx_bytes = ktx_texture.write_to_vec()?;
+
+    if let Some(verify_config) = &config.verify {
+        let report = measure_quality(&ktx_bytes, &source_images, shape)?;
+        verify_config
+            .check(&report)
+            .map_err(|reason| KtxError::Verify { path: None, reason })?;
+    }
+    Ok(ktx_bytes)
+}
+
+#[cfg(feature = "libktx")]
+fn canonicalize_write_path(image_write_path: &Path) -> Result<PathBuf, KtxError> {
+    let (Some(parent), Some(file_name)) = (image_write_path.parent(), image_write_path.file_name())
+    else {
+        return Err(
+            KtxError::invalid_config("Write path must name a file").with_path(image_write_path)
+        );
+    };
+    let parent = parent
+        .canonicalize()
+        .map_err(|err| KtxError::io(KtxStage::Write, image_write_path, err))?;
+    Ok(parent.join(file_name))
+}
+
+/// Writes the key/value pairs declared by `config.metadata`. `SourcePath` lists every source
+/// image, one per line, and `Premultiplied` matches the flag of the data format descriptor
+#[cfg(feature = "libktx")]
+fn apply_metadata(
+    ktx_texture: &mut KtxTexture<ImageSetInMemory>,
+    config: &CompressionConfig,
+    image_input_paths: &[&Path],
+    alpha_premultiplied: bool,
+) -> Result<(), KtxError> {
+    let Some(metadata_config) = &config.metadata else {
+        return Ok(());
     };
+
+    if let Some(orientation) = &metadata_config.orientation {
+        ktx_texture.set_orientation(orientation)?;
+    }
+    if let Some(writer) = &metadata_config.writer {
+        ktx_texture.set_writer(writer)?;
+    }
+    if let Some(swizzle) = &metadata_config.swizzle {
+        ktx_texture.set_swizzle(swizzle)?;
+    }
+    if metadata_config.source_path && !image_input_paths.is_empty() {
+        let source_paths = image_input_paths
+            .iter()
+            .map(|image_input_path| image_input_path.display().to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        ktx_texture.set_metadata(SOURCE_PATH_KEY, &MetadataValue::String(source_paths))?;
+    }
+    if metadata_config.premultiplied {
+        let premultiplied = alpha_premultiplied.to_string();
+        ktx_texture.set_metadata(PREMULTIPLIED_KEY, &MetadataValue::String(premultiplied))?;
+    }
+    for (key, value) in &metadata_config.custom {
+        ktx_texture.set_metadata(key, value)?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "libktx")]
+fn write_ktx_file(
+    ktx_bytes: Vec<u8>,
+    mut canonicalized_path: PathBuf,
+    image_input_path: &Path,
+) -> Result<(), KtxError> {
     if !canonicalized_path.set_extension("ktx") {
-        return Err(format!(
-            "Error changing image {} extension to ktx",
-            image_input_path.display()
-        )
-        .into());
+        return Err(
+            KtxError::invalid_config("Error changing image extension to ktx")
+                .with_path(image_input_path),
+        );
     }
+    write(&canonicalized_path, ktx_bytes)
+        .map_err(|err| KtxError::io(KtxStage::Write, &canonicalized_path, err))
+}
 
-    ktx_texture
-        .write_image_to_disk(&canonicalized_path)
-        .map_err(|result| {
-            LocalError::from(format!(
-                "Error writing image at {}: {}",
-                image_input_path.display(),
-                String::from(result)
-            ))
-        })?;
+#[cfg(all(test, feature = "libktx"))]
+mod test {
+    use std::{
+        fs::{read, write},
+        path::Path,
+    };
 
-    Ok(())
+    use crate::{
+        config::CompressionConfig, encode_texture, ktx_texture::KtxTexture,
+        ktx_types::TranscodeTarget, source_image::SourceImage, transcode_file,
+    };
+
+    fn encode(config_json: &str) -> Vec<u8> {
+        let config: CompressionConfig = serde_json::from_str(config_json).unwrap();
+        let pixels = (0..8 * 8 * 4).map(|value| value as u8).collect::<Vec<_>>();
+        encode_texture(SourceImage::from_rgba8(8, 8, pixels).unwrap(), &config).unwrap()
+    }
+
+    #[test]
+    fn transcode_file_copies_textures_that_are_not_basis() {
+        let (input_directory, output_directory) =
+            (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let basis_bytes = encode(
+            r#"{ "config_type": "BasisUniversalUASTC", "config": { "BasisUniversalUASTC": {} } }"#,
+        );
+        let zstd_bytes =
+            encode(r#"{ "config_type": "Zstd", "config": { "Zstd": { "deflation_value": 5 } } }"#);
+        write(input_directory.path().join("basis.ktx2"), &basis_bytes).unwrap();
+        write(input_directory.path().join("zstd.ktx2"), &zstd_bytes).unwrap();
+
+        let transcode = |file_name: &str, output_directory: &Path| {
+            transcode_file(
+                &input_directory.path().join(file_name),
+                TranscodeTarget::Rgba32,
+                &output_directory.join(file_name),
+            )
+            .unwrap()
+        };
+        assert!(transcode("basis.ktx2", output_directory.path()));
+        assert!(!transcode("zstd.ktx2", output_directory.path()));
+
+        let transcoded_bytes = read(output_directory.path().join("basis.ktx2")).unwrap();
+        assert!(!KtxTexture::from_bytes(&transcoded_bytes)
+            .unwrap()
+            .needs_transcoding());
+        assert_eq!(
+            read(output_directory.path().join("zstd.ktx2")).unwrap(),
+            zstd_bytes
+        );
+
+        // In place, a texture that is not Basis is left as it is
+        assert!(!transcode("zstd.ktx2", input_directory.path()));
+        assert_eq!(
+            read(input_directory.path().join("zstd.ktx2")).unwrap(),
+            zstd_bytes
+        );
+    }
 }
@@ -0,0 +1,170 @@
+use std::collections::BTreeMap;
+
+use lazy_regex::{lazy_regex, Lazy, Regex};
+use serde::{de::Error, Deserialize, Deserializer, Serialize};
+
+pub const KTX_ORIENTATION_KEY: &str = "KTXorientation";
+pub const KTX_WRITER_KEY: &str = "KTXwriter";
+pub const KTX_SWIZZLE_KEY: &str = "KTXswizzle";
+pub const SOURCE_PATH_KEY: &str = "SourcePath";
+pub const PREMULTIPLIED_KEY: &str = "Premultiplied";
+
+static ORIENTATION_REGEX: Lazy<Regex> = lazy_regex!("^[rl]([du][oi]?)?$");
+static SWIZZLE_REGEX: Lazy<Regex> = lazy_regex!("^[rgba01]{4}$");
+
+/// Value of a key/value pair. Strings are stored NUL terminated as the KTX2 spec requires for
+/// its own keys, anything else is stored as raw bytes
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MetadataValue {
+    String(String),
+    Bytes(Vec<u8>),
+}
+
+impl MetadataValue {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Self::String(string) => {
+                let mut bytes = Vec::with_capacity(string.len() + 1);
+                bytes.extend_from_slice(string.as_bytes());
+                bytes.push(0);
+                bytes
+            }
+            Self::Bytes(bytes) => bytes.clone(),
+        }
+    }
+
+    /// Reads a value back out of a texture. NUL terminated UTF-8 is returned as a string
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        match bytes.split_last() {
+            Some((0, string_bytes)) => match std::str::from_utf8(string_bytes) {
+                Ok(string) => Self::String(string.to_string()),
+                Err(_) => Self::Bytes(bytes.to_vec()),
+            },
+            _ => Self::Bytes(bytes.to_vec()),
+        }
+    }
+}
+
+impl From<&str> for MetadataValue {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_string())
+    }
+}
+
+/// Checks a `KTXorientation` value, one letter per dimension: `r`/`l`, then `d`/`u`, then `o`/`i`
+pub fn is_valid_orientation(orientation: &str) -> bool {
+    ORIENTATION_REGEX.is_match(orientation)
+}
+
+/// Checks a `KTXswizzle` value, four of `r`, `g`, `b`, `a`, `0` or `1`
+pub fn is_valid_swizzle(swizzle: &str) -> bool {
+    SWIZZLE_REGEX.is_match(swizzle)
+}
+
+/// Keys starting with `KTX` or `ktx` are reserved for the KTX2 spec
+pub fn is_reserved_key(key: &str) -> bool {
+    key.get(..3)
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case("ktx"))
+}
+
+/// Key/value pairs written into every texture built from a `CompressionConfig`
+#[serde_with::skip_serializing_none]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MetadataConfig {
+    #[serde(default, deserialize_with = "deserialize_orientation")]
+    pub orientation: Option<String>,
+    pub writer: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_swizzle")]
+    pub swizzle: Option<String>,
+    /// Writes the path of the source image(s) under `SourcePath`
+    #[serde(default)]
+    pub source_path: bool,
+    /// Writes whether the color channels were premultiplied under `Premultiplied`
+    #[serde(default)]
+    pub premultiplied: bool,
+    #[serde(default, deserialize_with = "deserialize_custom")]
+    pub custom: BTreeMap<String, MetadataValue>,
+}
+
+fn deserialize_orientation<'a, D: Deserializer<'a>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    let orientation = Option::<String>::deserialize(deserializer)?;
+    match orientation {
+        Some(orientation) if !is_valid_orientation(&orientation) => Err(Error::custom(format!(
+            "orientation {orientation} does not match regex, {}",
+            ORIENTATION_REGEX.as_str()
+        ))),
+        orientation => Ok(orientation),
+    }
+}
+
+fn deserialize_swizzle<'a, D: Deserializer<'a>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    let swizzle = Option::<String>::deserialize(deserializer)?;
+    match swizzle {
+        Some(swizzle) if !is_valid_swizzle(&swizzle) => Err(Error::custom(format!(
+            "swizzle {swizzle} does not match regex, {}",
+            SWIZZLE_REGEX.as_str()
+        ))),
+        swizzle => Ok(swizzle),
+    }
+}
+
+fn deserialize_custom<'a, D: Deserializer<'a>>(
+    deserializer: D,
+) -> Result<BTreeMap<String, MetadataValue>, D::Error> {
+    let custom = BTreeMap::<String, MetadataValue>::deserialize(deserializer)?;
+    if let Some(key) = custom.keys().find(|key| is_reserved_key(key)) {
+        return Err(Error::custom(format!(
+            "custom metadata key {key} is reserved, keys starting with KTX or ktx belong to the KTX2 spec"
+        )));
+    }
+    Ok(custom)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{is_reserved_key, is_valid_orientation, MetadataConfig, MetadataValue};
+
+    #[test]
+    fn metadata_values_round_trip() {
+        let string_value = MetadataValue::from("0.5,0.5");
+        assert_eq!(string_value.to_bytes(), b"0.5,0.5\0");
+        assert_eq!(
+            MetadataValue::from_bytes(&string_value.to_bytes()),
+            string_value
+        );
+
+        let bytes_value = MetadataValue::Bytes(vec![1, 2, 3]);
+        assert_eq!(
+            MetadataValue::from_bytes(&bytes_value.to_bytes()),
+            bytes_value
+        );
+    }
+
+    #[test]
+    fn metadata_config_validates_keys() {
+        assert!(is_valid_orientation("rd"));
+        assert!(is_valid_orientation("rdi"));
+        assert!(!is_valid_orientation("dr"));
+        assert!(is_reserved_key("KTXwriter"));
+        assert!(is_reserved_key("ktx_custom"));
+        assert!(!is_reserved_key("pivot"));
+
+        let config: MetadataConfig = serde_json::from_str(
+            r#"{ "orientation": "ru", "custom": { "pivot": "0.5,0.5", "flags": [1, 2] } }"#,
+        )
+        .unwrap();
+        assert_eq!(config.orientation.as_deref(), Some("ru"));
+        assert_eq!(config.custom["flags"], MetadataValue::Bytes(vec![1, 2]));
+
+        assert!(serde_json::from_str::<MetadataConfig>(r#"{ "orientation": "up" }"#).is_err());
+        assert!(
+            serde_json::from_str::<MetadataConfig>(r#"{ "custom": { "KTXwriter": "me" } }"#)
+                .is_err()
+        );
+    }
+}
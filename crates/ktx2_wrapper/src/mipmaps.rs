@@ -0,0 +1,435 @@
+use std::f32::consts::PI;
+
+use half::f16;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    color_space::{linear_to_srgb, srgb_to_linear, ColorSpace},
+    source_image::{PixelFormat, SourceImage},
+};
+
+const LANCZOS_SUPPORT: f32 = 3.;
+const KAISER_SUPPORT: f32 = 3.;
+const KAISER_ALPHA: f32 = 4.;
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum MipmapFilter {
+    Box,
+    #[default]
+    Kaiser,
+    Lanczos,
+}
+
+impl MipmapFilter {
+    /// Radius of the filter kernel, in source pixels of a 2x downsample
+    fn support(&self) -> f32 {
+        match self {
+            Self::Box => 0.5,
+            Self::Kaiser => KAISER_SUPPORT,
+            Self::Lanczos => LANCZOS_SUPPORT,
+        }
+    }
+
+    fn weight(&self, x: f32) -> f32 {
+        let x = x.abs();
+        match self {
+            Self::Box => {
+                if x <= 0.5 {
+                    1.
+                } else {
+                    0.
+                }
+            }
+            Self::Kaiser => {
+                if x >= KAISER_SUPPORT {
+                    return 0.;
+                }
+                let ratio = x / KAISER_SUPPORT;
+                sinc(x) * bessel_i0(KAISER_ALPHA * (1. - ratio * ratio).sqrt())
+                    / bessel_i0(KAISER_ALPHA)
+            }
+            Self::Lanczos => {
+                if x >= LANCZOS_SUPPORT {
+                    return 0.;
+                }
+                sinc(x) * sinc(x / LANCZOS_SUPPORT)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MipmapConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub filter: MipmapFilter,
+    #[serde(default = "default_min_dimension")]
+    pub min_dimension: u32,
+}
+
+impl Default for MipmapConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            filter: MipmapFilter::default(),
+            min_dimension: default_min_dimension(),
+        }
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_min_dimension() -> u32 {
+    1
+}
+
+impl MipmapConfig {
+    /// Number of levels in the chain, including the base level. Levels stop once the largest side
+    /// of the next level would drop below `min_dimension`
+    pub fn level_count(&self, width: u32, height: u32) -> u32 {
+        if !self.enabled {
+            return 1;
+        }
+        let min_dimension = self.min_dimension.max(1);
+        let mut level_count = 1;
+        let (mut level_width, mut level_height) = (width, height);
+        while level_width > 1 || level_height > 1 {
+            level_width = (level_width / 2).max(1);
+            level_height = (level_height / 2).max(1);
+            if level_width.max(level_height) < min_dimension {
+                break;
+            }
+            level_count += 1;
+        }
+        level_count
+    }
+}
+
+/// Returns the dimensions of `level` for a texture whose base level is `width` x `height`
+pub fn level_dimensions(width: u32, height: u32, level: u32) -> (u32, u32) {
+    ((width >> level).max(1), (height >> level).max(1))
+}
+
+/// Builds every level after the base level. `image_data` is the interleaved 8 bit base level with
+/// `channels` components per pixel. Color channels of sRGB textures are filtered in linear space;
+/// if there are 4 channels the last one is treated as alpha and is never gamma decoded
+pub fn generate_mip_chain(
+    width: u32,
+    height: u32,
+    channels: usize,
+    image_data: &[u8],
+    config: &MipmapConfig,
+    color_space: ColorSpace,
+) -> Vec<Vec<u8>> {
+    let alpha_channel = (channels == 4).then_some(3);
+    let is_color_channel =
+        |channel: usize| color_space == ColorSpace::Srgb && Some(channel) != alpha_channel;
+
+    let base_level = image_data
+        .chunks_exact(channels)
+        .flat_map(|pixel| {
+            pixel.iter().enumerate().map(|(channel, value)| {
+                let value = *value as f32 / 255.;
+                if is_color_channel(channel) {
+                    srgb_to_linear(value)
+                } else {
+                    value
+                }
+            })
+        })
+        .collect::<Vec<f32>>();
+
+    downsample_chain(width, height, channels, base_level, config)
+        .into_iter()
+        .map(|level| {
+            level
+                .chunks_exact(channels)
+                .flat_map(|pixel| {
+                    pixel.iter().enumerate().map(|(channel, value)| {
+                        let value = if is_color_channel(channel) {
+                            linear_to_srgb(value.clamp(0., 1.))
+                        } else {
+                            *value
+                        };
+                        (value.clamp(0., 1.) * 255.).round() as u8
+                    })
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Builds every level after the base level of `image`, in the same pixel format. `color_space` is
+/// the effective color space of the texture. Only 8 bit images are gamma decoded, wider formats
+/// are always stored linear
+pub fn generate_source_mip_chain(
+    image: &SourceImage,
+    config: &MipmapConfig,
+    color_space: ColorSpace,
+) -> Vec<Vec<u8>> {
+    let channels = image.pixel_format.channels();
+    match image.pixel_format {
+        PixelFormat::R8 | PixelFormat::Rg8 | PixelFormat::Rgb8 | PixelFormat::Rgba8 => {
+            generate_mip_chain(
+                image.width,
+                image.height,
+                channels,
+                &image.data,
+                config,
+                color_space,
+            )
+        }
+        PixelFormat::Rgba16 => {
+            let base_level = image
+                .data
+                .chunks_exact(2)
+                .map(|sample| u16::from_le_bytes([sample[0], sample[1]]) as f32 / 65535.)
+                .collect();
+            downsample_chain(image.width, image.height, channels, base_level, config)
+                .into_iter()
+                .map(|level| {
+                    level
+                        .iter()
+                        .flat_map(|value| {
+                            ((value.clamp(0., 1.) * 65535.).round() as u16).to_le_bytes()
+                        })
+                        .collect()
+                })
+                .collect()
+        }
+        PixelFormat::Rgba16Float => {
+            let base_level = image
+                .data
+                .chunks_exact(2)
+                .map(|sample| f16::from_le_bytes([sample[0], sample[1]]).to_f32())
+                .collect();
+            downsample_chain(image.width, image.height, channels, base_level, config)
+                .into_iter()
+                .map(|level| {
+                    level
+                        .iter()
+                        .flat_map(|value| f16::from_f32(value.max(0.)).to_le_bytes())
+                        .collect()
+                })
+                .collect()
+        }
+    }
+}
+
+/// Repeatedly halves `base_level`, returning every level after the base level as linear floats
+fn downsample_chain(
+    width: u32,
+    height: u32,
+    channels: usize,
+    base_level: Vec<f32>,
+    config: &MipmapConfig,
+) -> Vec<Vec<f32>> {
+    let level_count = config.level_count(width, height);
+    let mut levels = Vec::with_capacity(level_count.saturating_sub(1) as usize);
+    let mut current_level = base_level;
+    let (mut current_width, mut current_height) = (width as usize, height as usize);
+    for level in 1..level_count {
+        let (next_width, next_height) = level_dimensions(width, height, level);
+        let (next_width, next_height) = (next_width as usize, next_height as usize);
+
+        let horizontal = resample_horizontal(
+            &current_level,
+            current_width,
+            current_height,
+            next_width,
+            channels,
+            config.filter,
+        );
+        current_level = resample_vertical(
+            &horizontal,
+            next_width,
+            current_height,
+            next_height,
+            channels,
+            config.filter,
+        );
+        current_width = next_width;
+        current_height = next_height;
+        levels.push(current_level.clone());
+    }
+    levels
+}
+
+/// Precomputed contribution of source pixels to a single destination pixel
+struct Contribution {
+    first_source_index: usize,
+    weights: Vec<f32>,
+}
+
+fn contributions(
+    source_len: usize,
+    destination_len: usize,
+    filter: MipmapFilter,
+) -> Vec<Contribution> {
+    let scale = source_len as f32 / destination_len as f32;
+    let support = filter.support() * scale;
+    (0..destination_len)
+        .map(|destination_index| {
+            let center = (destination_index as f32 + 0.5) * scale;
+            let left = (center - support).floor().max(0.) as usize;
+            let right = ((center + support).ceil() as usize).min(source_len);
+            let mut weights = (left..right)
+                .map(|source_index| filter.weight((source_index as f32 + 0.5 - center) / scale))
+                .collect::<Vec<f32>>();
+            let total_weight = weights.iter().sum::<f32>();
+            if total_weight.abs() > f32::EPSILON {
+                for weight in &mut weights {
+                    *weight /= total_weight;
+                }
+            }
+            Contribution {
+                first_source_index: left,
+                weights,
+            }
+        })
+        .collect()
+}
+
+fn resample_horizontal(
+    source: &[f32],
+    source_width: usize,
+    height: usize,
+    destination_width: usize,
+    channels: usize,
+    filter: MipmapFilter,
+) -> Vec<f32> {
+    let contributions = contributions(source_width, destination_width, filter);
+    let mut destination = vec![0.; destination_width * height * channels];
+    for y in 0..height {
+        let source_row = &source[y * source_width * channels..(y + 1) * source_width * channels];
+        let destination_row = &mut destination
+            [y * destination_width * channels..(y + 1) * destination_width * channels];
+        for (x, contribution) in contributions.iter().enumerate() {
+            for (offset, weight) in contribution.weights.iter().enumerate() {
+                let source_x = contribution.first_source_index + offset;
+                for channel in 0..channels {
+                    destination_row[x * channels + channel] +=
+                        source_row[source_x * channels + channel] * weight;
+                }
+            }
+        }
+    }
+    destination
+}
+
+fn resample_vertical(
+    source: &[f32],
+    width: usize,
+    source_height: usize,
+    destination_height: usize,
+    channels: usize,
+    filter: MipmapFilter,
+) -> Vec<f32> {
+    let contributions = contributions(source_height, destination_height, filter);
+    let row_len = width * channels;
+    let mut destination = vec![0.; row_len * destination_height];
+    for (y, contribution) in contributions.iter().enumerate() {
+        let destination_row = &mut destination[y * row_len..(y + 1) * row_len];
+        for (offset, weight) in contribution.weights.iter().enumerate() {
+            let source_y = contribution.first_source_index + offset;
+            let source_row = &source[source_y * row_len..(source_y + 1) * row_len];
+            for (destination_value, source_value) in destination_row.iter_mut().zip(source_row) {
+                *destination_value += source_value * weight;
+            }
+        }
+    }
+    destination
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < f32::EPSILON {
+        1.
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// Zeroth order modified Bessel function of the first kind, used by the Kaiser window
+fn bessel_i0(x: f32) -> f32 {
+    let half_x_squared = (x / 2.) * (x / 2.);
+    let mut sum = 1.;
+    let mut term = 1.;
+    let mut k = 1.;
+    while term > sum * 1e-8 {
+        term *= half_x_squared / (k * k);
+        sum += term;
+        k += 1.;
+    }
+    sum
+}
+
+#[cfg(test)]
+mod test {
+    use super::{generate_mip_chain, level_dimensions, MipmapConfig, MipmapFilter};
+    use crate::color_space::ColorSpace;
+
+    #[test]
+    fn level_count_covers_full_chain() {
+        let config = MipmapConfig::default();
+        assert_eq!(config.level_count(256, 256), 9);
+        assert_eq!(config.level_count(256, 64), 9);
+        assert_eq!(config.level_count(1, 1), 1);
+        assert_eq!(level_dimensions(256, 64, 7), (2, 1));
+    }
+
+    #[test]
+    fn level_count_respects_min_dimension() {
+        let config = MipmapConfig {
+            min_dimension: 16,
+            ..Default::default()
+        };
+        assert_eq!(config.level_count(256, 256), 5);
+
+        let config = MipmapConfig {
+            enabled: false,
+            ..Default::default()
+        };
+        assert_eq!(config.level_count(256, 256), 1);
+    }
+
+    #[test]
+    fn flat_image_stays_flat_for_every_filter() {
+        let base_level = [200, 100, 50, 255].repeat(16 * 8);
+        for filter in [
+            MipmapFilter::Box,
+            MipmapFilter::Kaiser,
+            MipmapFilter::Lanczos,
+        ] {
+            let config = MipmapConfig {
+                filter,
+                ..Default::default()
+            };
+            let levels = generate_mip_chain(16, 8, 4, &base_level, &config, ColorSpace::Srgb);
+            assert_eq!(levels.len(), 4);
+            for (level_index, level) in levels.iter().enumerate() {
+                let (width, height) = level_dimensions(16, 8, level_index as u32 + 1);
+                assert_eq!(level.len(), (width * height * 4) as usize);
+                for pixel in level.chunks_exact(4) {
+                    assert_eq!(pixel, [200, 100, 50, 255]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn srgb_box_filter_averages_in_linear_space() {
+        let base_level = [0, 0, 0, 255, 255, 255, 255, 255].repeat(2);
+        let config = MipmapConfig {
+            filter: MipmapFilter::Box,
+            ..Default::default()
+        };
+        let levels = generate_mip_chain(2, 2, 4, &base_level, &config, ColorSpace::Srgb);
+        assert_eq!(levels[0], [188, 188, 188, 255]);
+
+        let levels = generate_mip_chain(2, 2, 4, &base_level, &config, ColorSpace::Linear);
+        assert_eq!(levels[0], [128, 128, 128, 255]);
+    }
+}
@@ -0,0 +1,370 @@
+use std::{fs::read, path::Path};
+
+use exr::prelude::read_first_rgba_layer_from_file;
+use half::f16;
+use serde::{Deserialize, Serialize};
+use shared_types::{LocalError, SupportedImages};
+use strum::Display;
+use zune_core::{
+    colorspace::ColorSpace as ZuneColorSpace, options::DecoderOptions, result::DecodingResult,
+};
+use zune_hdr::HdrDecoder;
+use zune_jpeg::JpegDecoder;
+use zune_png::PngDecoder;
+
+#[cfg(feature = "libktx")]
+use crate::ffi::VkFormat;
+use crate::{alpha, color_space::ColorSpace, error::KtxError};
+
+/// Layout of the pixels in a `SourceImage`. Multi-byte samples are stored little endian
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PixelFormat {
+    R8,
+    Rg8,
+    Rgb8,
+    Rgba8,
+    Rgba16,
+    Rgba16Float,
+}
+
+impl PixelFormat {
+    pub fn channels(&self) -> usize {
+        match self {
+            Self::R8 => 1,
+            Self::Rg8 => 2,
+            Self::Rgb8 => 3,
+            Self::Rgba8 | Self::Rgba16 | Self::Rgba16Float => 4,
+        }
+    }
+
+    pub fn bytes_per_sample(&self) -> usize {
+        if self.is_8_bit() {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// Only RGBA formats carry alpha, which is always the last channel
+    pub fn has_alpha(&self) -> bool {
+        self.channels() == 4
+    }
+
+    pub fn is_8_bit(&self) -> bool {
+        matches!(self, Self::R8 | Self::Rg8 | Self::Rgb8 | Self::Rgba8)
+    }
+
+    pub fn bytes_per_pixel(&self) -> usize {
+        self.channels() * self.bytes_per_sample()
+    }
+
+    /// Only 8 bit formats have sRGB variants, everything else is stored linear
+    pub fn effective_color_space(&self, color_space: ColorSpace) -> ColorSpace {
+        if self.is_8_bit() {
+            color_space
+        } else {
+            ColorSpace::Linear
+        }
+    }
+
+    #[cfg(feature = "libktx")]
+    pub fn vk_format(&self, color_space: ColorSpace) -> VkFormat {
+        match (self, color_space) {
+            (Self::R8, ColorSpace::Srgb) => VkFormat::VK_FORMAT_R8_SRGB,
+            (Self::R8, ColorSpace::Linear) => VkFormat::VK_FORMAT_R8_UNORM,
+            (Self::Rg8, ColorSpace::Srgb) => VkFormat::VK_FORMAT_R8G8_SRGB,
+            (Self::Rg8, ColorSpace::Linear) => VkFormat::VK_FORMAT_R8G8_UNORM,
+            (Self::Rgb8, ColorSpace::Srgb) => VkFormat::VK_FORMAT_R8G8B8_SRGB,
+            (Self::Rgb8, ColorSpace::Linear) => VkFormat::VK_FORMAT_R8G8B8_UNORM,
+            (Self::Rgba8, _) => color_space.rgba8_format(),
+            (Self::Rgba16, _) => VkFormat::VK_FORMAT_R16G16B16A16_UNORM,
+            (Self::Rgba16Float, _) => VkFormat::VK_FORMAT_R16G16B16A16_SFLOAT,
+        }
+    }
+
+    pub fn is_hdr(&self) -> bool {
+        matches!(self, Self::Rgba16Float)
+    }
+}
+
+/// Channels 8 bit PNG and JPEG sources are stored with. `Auto` keeps the channels of the source:
+/// grayscale images become R8, grayscale images with alpha become RG8 with alpha in green, RGB
+/// images become RGB8 and RGBA images RGBA8. Missing channels are filled from the luminance, and
+/// alpha from opaque. 16 bit and floating point sources are always stored as RGBA
+#[derive(Clone, Copy, Debug, Default, Display, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Channels {
+    Auto,
+    R,
+    Rg,
+    Rgb,
+    #[default]
+    Rgba,
+}
+
+impl Channels {
+    /// The 8 bit format to store a source with `source_channels` channels in
+    pub fn pixel_format(&self, source_channels: usize) -> PixelFormat {
+        match (self, source_channels) {
+            (Self::R, _) | (Self::Auto, 1) => PixelFormat::R8,
+            (Self::Rg, _) | (Self::Auto, 2) => PixelFormat::Rg8,
+            (Self::Rgb, _) | (Self::Auto, 3) => PixelFormat::Rgb8,
+            (Self::Rgba | Self::Auto, _) => PixelFormat::Rgba8,
+        }
+    }
+}
+
+/// Decoded pixels of a source image, ready to be uploaded into a texture
+#[derive(Clone, Debug)]
+pub struct SourceImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixel_format: PixelFormat,
+    pub data: Vec<u8>,
+}
+
+impl SourceImage {
+    /// Wraps pixels that are already in memory, `data` must hold exactly `width` x `height` pixels
+    /// of `pixel_format`
+    pub fn new(
+        width: u32,
+        height: u32,
+        pixel_format: PixelFormat,
+        data: Vec<u8>,
+    ) -> Result<Self, KtxError> {
+        let expected_len = width as usize * height as usize * pixel_format.bytes_per_pixel();
+        if width == 0 || height == 0 || data.len() != expected_len {
+            return Err(KtxError::invalid_config(format!(
+                "{width}x{height} {pixel_format:?} image needs {expected_len} bytes but {} were given",
+                data.len()
+            )));
+        }
+        Ok(Self {
+            width,
+            height,
+            pixel_format,
+            data,
+        })
+    }
+
+    pub fn from_rgba8(width: u32, height: u32, data: Vec<u8>) -> Result<Self, KtxError> {
+        Self::new(width, height, PixelFormat::Rgba8, data)
+    }
+
+    /// Decodes `image_path`, storing 8 bit sources with `channels`
+    pub fn open(
+        image_path: &Path,
+        image_type: &SupportedImages,
+        channels: Channels,
+    ) -> Result<Self, LocalError> {
+        match image_type {
+            SupportedImages::Png => decode_png(image_path, channels),
+            SupportedImages::Jpeg => decode_jpeg(image_path, channels),
+            SupportedImages::Hdr => decode_hdr(image_path),
+            SupportedImages::Exr => decode_exr(image_path),
+        }
+    }
+
+    /// Multiplies the color channels by alpha in linear space, `color_space` tells how 8 bit color
+    /// channels are encoded. Only RGBA formats have an alpha channel, every other format is left
+    /// untouched
+    pub fn premultiply_alpha(&mut self, color_space: ColorSpace) {
+        match self.pixel_format {
+            PixelFormat::R8 | PixelFormat::Rg8 | PixelFormat::Rgb8 => {}
+            PixelFormat::Rgba8 => alpha::premultiply_rgba8(&mut self.data, color_space),
+            PixelFormat::Rgba16 => alpha::premultiply_rgba16(&mut self.data),
+            PixelFormat::Rgba16Float => alpha::premultiply_rgba16_float(&mut self.data),
+        }
+    }
+}
+
+fn decode_png(png_path: &Path, channels: Channels) -> Result<SourceImage, LocalError> {
+    let png_file_contents = read(png_path)?;
+
+    let decoder_options = DecoderOptions::default().png_set_strip_to_8bit(false);
+    let mut png_decoder =
+        PngDecoder::new_with_options(png_file_contents.as_slice(), decoder_options);
+    let png_data = png_decoder.decode()?;
+    let (width, height) = png_decoder.get_dimensions().ok_or(format!(
+        "Cannot get dimensions for png: {}",
+        png_path.display()
+    ))?;
+    let source_channels = png_decoder
+        .get_colorspace()
+        .map(|colorspace| colorspace.num_components())
+        .filter(|components| (1..=4).contains(components))
+        .ok_or(format!(
+            "Unsupported colorspace in png: {}",
+            png_path.display()
+        ))?;
+    let (pixel_format, data) = match png_data {
+        DecodingResult::U8(data) => {
+            let pixel_format = channels.pixel_format(source_channels);
+            (
+                pixel_format,
+                select_channels(&data, source_channels, pixel_format),
+            )
+        }
+        DecodingResult::U16(data) => (
+            PixelFormat::Rgba16,
+            data.chunks_exact(source_channels)
+                .flat_map(|pixel| expand_to_rgba(pixel, u16::MAX))
+                .flat_map(|sample| sample.to_le_bytes())
+                .collect(),
+        ),
+        _ => return Err(format!("Unsupported sample type in png: {}", png_path.display()).into()),
+    };
+    Ok(SourceImage {
+        width: width as u32,
+        height: height as u32,
+        pixel_format,
+        data,
+    })
+}
+
+fn decode_jpeg(jpeg_path: &Path, channels: Channels) -> Result<SourceImage, LocalError> {
+    let jpeg_file_contents = read(jpeg_path)?;
+
+    let mut jpeg_decoder = JpegDecoder::new(jpeg_file_contents.as_slice());
+    jpeg_decoder.decode_headers()?;
+    let out_colorspace = match jpeg_decoder.get_input_colorspace() {
+        Some(ZuneColorSpace::Luma) => ZuneColorSpace::Luma,
+        _ => ZuneColorSpace::RGB,
+    };
+    jpeg_decoder.set_options(DecoderOptions::default().jpeg_set_out_colorspace(out_colorspace));
+    let jpeg_data = jpeg_decoder.decode()?;
+    let (width, height) = jpeg_decoder
+        .dimensions()
+        .ok_or(LocalError::from("JPEG decoding failed"))?;
+    let source_channels = out_colorspace.num_components();
+    let pixel_format = channels.pixel_format(source_channels);
+    Ok(SourceImage {
+        width: width as u32,
+        height: height as u32,
+        pixel_format,
+        data: select_channels(&jpeg_data, source_channels, pixel_format),
+    })
+}
+
+/// Expands a grayscale, grayscale alpha, RGB or RGBA pixel to RGBA
+fn expand_to_rgba<T: Copy>(pixel: &[T], opaque: T) -> [T; 4] {
+    match *pixel {
+        [luma] => [luma, luma, luma, opaque],
+        [luma, alpha] => [luma, luma, luma, alpha],
+        [red, green, blue] => [red, green, blue, opaque],
+        [red, green, blue, alpha, ..] => [red, green, blue, alpha],
+        [] => [opaque; 4],
+    }
+}
+
+/// Converts interleaved 8 bit pixels with `source_channels` channels to `pixel_format`. The alpha
+/// of grayscale alpha sources goes into green when stored as RG8
+fn select_channels(data: &[u8], source_channels: usize, pixel_format: PixelFormat) -> Vec<u8> {
+    if source_channels == pixel_format.channels() {
+        return data.to_vec();
+    }
+    let mut selected = Vec::with_capacity(data.len() / source_channels * pixel_format.channels());
+    for pixel in data.chunks_exact(source_channels) {
+        let [red, green, blue, alpha] = expand_to_rgba(pixel, u8::MAX);
+        match pixel_format {
+            PixelFormat::R8 => selected.push(red),
+            PixelFormat::Rg8 if source_channels == 2 => selected.extend_from_slice(&[red, alpha]),
+            PixelFormat::Rg8 => selected.extend_from_slice(&[red, green]),
+            PixelFormat::Rgb8 => selected.extend_from_slice(&[red, green, blue]),
+            _ => selected.extend_from_slice(&[red, green, blue, alpha]),
+        }
+    }
+    selected
+}
+
+fn decode_hdr(hdr_path: &Path) -> Result<SourceImage, LocalError> {
+    let hdr_file_contents = read(hdr_path)?;
+
+    let mut hdr_decoder = HdrDecoder::new(hdr_file_contents.as_slice());
+    let hdr_data = hdr_decoder.decode().map_err(|err| {
+        LocalError::from(format!(
+            "Radiance HDR decoding failed for {}: {err:?}",
+            hdr_path.display()
+        ))
+    })?;
+    let (width, height) = hdr_decoder.get_dimensions().ok_or(format!(
+        "Cannot get dimensions for hdr: {}",
+        hdr_path.display()
+    ))?;
+    let data = hdr_data
+        .chunks_exact(3)
+        .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 1.])
+        .flat_map(|sample| f16::from_f32(sample).to_le_bytes())
+        .collect();
+    Ok(SourceImage {
+        width: width as u32,
+        height: height as u32,
+        pixel_format: PixelFormat::Rgba16Float,
+        data,
+    })
+}
+
+struct ExrPixels {
+    width: usize,
+    samples: Vec<[f32; 4]>,
+}
+
+fn decode_exr(exr_path: &Path) -> Result<SourceImage, LocalError> {
+    let exr_image = read_first_rgba_layer_from_file(
+        exr_path,
+        |resolution, _| ExrPixels {
+            width: resolution.width(),
+            samples: vec![[0., 0., 0., 1.]; resolution.width() * resolution.height()],
+        },
+        |pixels: &mut ExrPixels, position, (red, green, blue, alpha): (f32, f32, f32, f32)| {
+            pixels.samples[position.y() * pixels.width + position.x()] = [red, green, blue, alpha];
+        },
+    )?;
+    let size = exr_image.layer_data.size;
+    let data = exr_image
+        .layer_data
+        .channel_data
+        .pixels
+        .samples
+        .iter()
+        .flatten()
+        .flat_map(|sample| f16::from_f32(*sample).to_le_bytes())
+        .collect();
+    Ok(SourceImage {
+        width: size.width() as u32,
+        height: size.height() as u32,
+        pixel_format: PixelFormat::Rgba16Float,
+        data,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{select_channels, Channels, PixelFormat};
+
+    #[test]
+    fn channels_follow_the_source_or_the_config() {
+        assert_eq!(Channels::Auto.pixel_format(1), PixelFormat::R8);
+        assert_eq!(Channels::Auto.pixel_format(2), PixelFormat::Rg8);
+        assert_eq!(Channels::Auto.pixel_format(3), PixelFormat::Rgb8);
+        assert_eq!(Channels::Auto.pixel_format(4), PixelFormat::Rgba8);
+        assert_eq!(Channels::Rg.pixel_format(4), PixelFormat::Rg8);
+        assert_eq!(Channels::default().pixel_format(1), PixelFormat::Rgba8);
+
+        let luma_alpha = [10, 200, 20, 100];
+        assert_eq!(
+            select_channels(&luma_alpha, 2, PixelFormat::Rg8),
+            luma_alpha
+        );
+        assert_eq!(
+            select_channels(&luma_alpha, 2, PixelFormat::Rgba8),
+            [10, 10, 10, 200, 20, 20, 20, 100]
+        );
+        let rgb = [1, 2, 3, 4, 5, 6];
+        assert_eq!(select_channels(&rgb, 3, PixelFormat::R8), [1, 4]);
+        assert_eq!(select_channels(&rgb, 3, PixelFormat::Rg8), [1, 2, 4, 5]);
+        assert_eq!(
+            select_channels(&rgb, 3, PixelFormat::Rgba8),
+            [1, 2, 3, 255, 4, 5, 6, 255]
+        );
+    }
+}
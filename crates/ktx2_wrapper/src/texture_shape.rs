@@ -0,0 +1,48 @@
+/// The layout of the images making up a texture. Cubemap faces are ordered +X, -X, +Y, -Y, +Z, -Z
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextureShape {
+    #[default]
+    Texture2D,
+    Cubemap,
+    Array {
+        layers: u32,
+    },
+    CubemapArray {
+        layers: u32,
+    },
+    Volume {
+        depth: u32,
+    },
+}
+
+impl TextureShape {
+    pub fn num_faces(&self) -> u32 {
+        match self {
+            Self::Cubemap | Self::CubemapArray { .. } => 6,
+            Self::Texture2D | Self::Array { .. } | Self::Volume { .. } => 1,
+        }
+    }
+
+    pub fn num_layers(&self) -> u32 {
+        match self {
+            Self::Array { layers } | Self::CubemapArray { layers } => (*layers).max(1),
+            Self::Texture2D | Self::Cubemap | Self::Volume { .. } => 1,
+        }
+    }
+
+    pub fn depth(&self) -> u32 {
+        match self {
+            Self::Volume { depth } => (*depth).max(1),
+            Self::Texture2D | Self::Cubemap | Self::Array { .. } | Self::CubemapArray { .. } => 1,
+        }
+    }
+
+    pub fn is_array(&self) -> bool {
+        matches!(self, Self::Array { .. } | Self::CubemapArray { .. })
+    }
+
+    /// The number of source images needed to fill the base level
+    pub fn image_count(&self) -> u32 {
+        self.num_layers() * self.num_faces() * self.depth()
+    }
+}
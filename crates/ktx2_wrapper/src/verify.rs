@@ -0,0 +1,327 @@
+//! Round-trip quality checks. An encoded texture is decoded, or transcoded to RGBA for Basis
+//! Universal, and its base level is compared channel by channel against the source pixels using
+//! PSNR and SSIM
+
+use half::f16;
+use serde::{Deserialize, Serialize};
+
+use crate::source_image::{PixelFormat, SourceImage};
+#[cfg(feature = "libktx")]
+use crate::{
+    error::KtxError,
+    ktx_texture::{KtxTexture, TextureShape},
+    ktx_types::TranscodeTarget,
+};
+
+/// Side of the square windows SSIM is averaged over
+const SSIM_WINDOW: usize = 8;
+const SSIM_C1: f64 = 0.01 * 0.01;
+const SSIM_C2: f64 = 0.03 * 0.03;
+
+/// Thresholds an encoded texture must meet, in the worst channel of every image
+#[serde_with::skip_serializing_none]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct VerifyConfig {
+    /// Minimum peak signal to noise ratio, in decibels
+    #[serde(default)]
+    pub min_psnr: Option<f64>,
+    /// Minimum structural similarity, from -1 to 1 where 1 is identical
+    #[serde(default)]
+    pub min_ssim: Option<f64>,
+}
+
+impl VerifyConfig {
+    pub fn check(&self, report: &QualityReport) -> Result<(), String> {
+        if let Some(min_psnr) = self.min_psnr {
+            if report.min_psnr() < min_psnr {
+                return Err(format!(
+                    "PSNR of {:.2} dB is below the minimum of {min_psnr} dB, {report}",
+                    report.min_psnr()
+                ));
+            }
+        }
+        if let Some(min_ssim) = self.min_ssim {
+            if report.min_ssim() < min_ssim {
+                return Err(format!(
+                    "SSIM of {:.4} is below the minimum of {min_ssim}, {report}",
+                    report.min_ssim()
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChannelQuality {
+    /// Infinite when the channel is identical to the source
+    pub psnr: f64,
+    pub ssim: f64,
+}
+
+/// Quality of every channel of the source, in channel order
+#[derive(Clone, Debug, PartialEq)]
+pub struct QualityReport {
+    pub channels: Vec<ChannelQuality>,
+}
+
+impl QualityReport {
+    pub fn min_psnr(&self) -> f64 {
+        self.channels
+            .iter()
+            .map(|channel| channel.psnr)
+            .fold(f64::INFINITY, f64::min)
+    }
+
+    pub fn min_ssim(&self) -> f64 {
+        self.channels
+            .iter()
+            .map(|channel| channel.ssim)
+            .fold(1., f64::min)
+    }
+
+    /// Keeps the worst value of each channel out of two reports
+    #[cfg(feature = "libktx")]
+    fn worst(self, other: Self) -> Self {
+        Self {
+            channels: self
+                .channels
+                .into_iter()
+                .zip(other.channels)
+                .map(|(channel, other_channel)| ChannelQuality {
+                    psnr: channel.psnr.min(other_channel.psnr),
+                    ssim: channel.ssim.min(other_channel.ssim),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl std::fmt::Display for QualityReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (index, channel) in self.channels.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            let name = ["r", "g", "b", "a"].get(index).unwrap_or(&"?");
+            write!(f, "{name}: {:.2} dB {:.4} SSIM", channel.psnr, channel.ssim)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares two images of the same dimensions and pixel format channel by channel. Samples are
+/// normalized to 0..1, floating point samples are compared as is
+pub fn compare_images(source: &SourceImage, decoded: &SourceImage) -> Option<QualityReport> {
+    if (source.width, source.height, source.pixel_format)
+        != (decoded.width, decoded.height, decoded.pixel_format)
+    {
+        return None;
+    }
+    let (width, height) = (source.width as usize, source.height as usize);
+    let channels = source.pixel_format.channels();
+    let (source_samples, decoded_samples) =
+        (normalized_samples(source), normalized_samples(decoded));
+    let channels = (0..channels)
+        .map(|channel| {
+            let plane = |samples: &[f32]| {
+                samples
+                    .iter()
+                    .skip(channel)
+                    .step_by(channels)
+                    .map(|sample| *sample as f64)
+                    .collect::<Vec<_>>()
+            };
+            let (source_plane, decoded_plane) = (plane(&source_samples), plane(&decoded_samples));
+            ChannelQuality {
+                psnr: psnr(&source_plane, &decoded_plane),
+                ssim: ssim(&source_plane, &decoded_plane, width, height),
+            }
+        })
+        .collect();
+    Some(QualityReport { channels })
+}
+
+/// Decodes `ktx_bytes` and compares the base level of every image with `source_images`, which are
+/// ordered like for `encode_texture_with_shape`. Basis Universal textures are transcoded to RGBA
+/// first, 1 and 2 channel sources are compared against the red, and red and alpha channels, the
+/// way libktx swizzles them before encoding. ASTC textures cannot be decoded by libktx and are
+/// rejected
+#[cfg(feature = "libktx")]
+pub fn measure_quality(
+    ktx_bytes: &[u8],
+    source_images: &[SourceImage],
+    shape: TextureShape,
+) -> Result<QualityReport, KtxError> {
+    let mut ktx_texture = KtxTexture::from_bytes(ktx_bytes)?;
+    let transcoded = ktx_texture.needs_transcoding();
+    if transcoded {
+        ktx_texture.transcode(TranscodeTarget::Rgba32)?;
+    } else if ktx_texture.info().is_compressed {
+        return Err(KtxError::invalid_config(
+            "Only uncompressed and Basis Universal textures can be verified",
+        ));
+    }
+
+    let slices_per_layer = shape.num_faces() * shape.depth();
+    let mut report: Option<QualityReport> = None;
+    for (image_index, source_image) in source_images.iter().enumerate() {
+        let layer = image_index as u32 / slices_per_layer;
+        let face_slice = image_index as u32 % slices_per_layer;
+        let image_data = ktx_texture.image_data(0, layer, face_slice)?;
+        let data = if transcoded {
+            rgba8_to_source_channels(image_data, source_image.pixel_format)
+        } else {
+            image_data.to_vec()
+        };
+        let decoded = SourceImage::new(
+            source_image.width,
+            source_image.height,
+            source_image.pixel_format,
+            data,
+        )?;
+        let image_report = compare_images(source_image, &decoded).ok_or_else(|| {
+            KtxError::invalid_config(format!("Image {image_index} does not match its source"))
+        })?;
+        report = Some(match report {
+            Some(report) => report.worst(image_report),
+            None => image_report,
+        });
+    }
+    report.ok_or_else(|| KtxError::invalid_config("There are no images to verify"))
+}
+
+/// Picks the channels of a transcoded RGBA8 image that hold the channels of `pixel_format`
+#[cfg(feature = "libktx")]
+fn rgba8_to_source_channels(rgba: &[u8], pixel_format: PixelFormat) -> Vec<u8> {
+    let channel_indices: &[usize] = match pixel_format.channels() {
+        1 => &[0],
+        2 => &[0, 3],
+        3 => &[0, 1, 2],
+        _ => &[0, 1, 2, 3],
+    };
+    rgba.chunks_exact(4)
+        .flat_map(|pixel| channel_indices.iter().map(|index| pixel[*index]))
+        .collect()
+}
+
+fn normalized_samples(image: &SourceImage) -> Vec<f32> {
+    match image.pixel_format {
+        PixelFormat::R8 | PixelFormat::Rg8 | PixelFormat::Rgb8 | PixelFormat::Rgba8 => image
+            .data
+            .iter()
+            .map(|sample| *sample as f32 / 255.)
+            .collect(),
+        PixelFormat::Rgba16 => image
+            .data
+            .chunks_exact(2)
+            .map(|sample| u16::from_le_bytes([sample[0], sample[1]]) as f32 / 65535.)
+            .collect(),
+        PixelFormat::Rgba16Float => image
+            .data
+            .chunks_exact(2)
+            .map(|sample| f16::from_le_bytes([sample[0], sample[1]]).to_f32())
+            .collect(),
+    }
+}
+
+fn psnr(source: &[f64], decoded: &[f64]) -> f64 {
+    let squared_error = source
+        .iter()
+        .zip(decoded)
+        .map(|(source, decoded)| (source - decoded).powi(2))
+        .sum::<f64>();
+    let mean_squared_error = squared_error / source.len().max(1) as f64;
+    if mean_squared_error == 0. {
+        f64::INFINITY
+    } else {
+        10. * (1. / mean_squared_error).log10()
+    }
+}
+
+/// Mean SSIM over non-overlapping windows, windows on the right and bottom edges may be smaller
+fn ssim(source: &[f64], decoded: &[f64], width: usize, height: usize) -> f64 {
+    let mut ssim_sum = 0.;
+    let mut window_count = 0;
+    for window_y in (0..height).step_by(SSIM_WINDOW) {
+        for window_x in (0..width).step_by(SSIM_WINDOW) {
+            let indices = (window_y..(window_y + SSIM_WINDOW).min(height)).flat_map(|y| {
+                (window_x..(window_x + SSIM_WINDOW).min(width)).map(move |x| y * width + x)
+            });
+            let sample_count = indices.clone().count() as f64;
+            let source_mean =
+                indices.clone().map(|index| source[index]).sum::<f64>() / sample_count;
+            let decoded_mean =
+                indices.clone().map(|index| decoded[index]).sum::<f64>() / sample_count;
+            let (mut source_variance, mut decoded_variance, mut covariance) = (0., 0., 0.);
+            for index in indices {
+                let source_delta = source[index] - source_mean;
+                let decoded_delta = decoded[index] - decoded_mean;
+                source_variance += source_delta * source_delta;
+                decoded_variance += decoded_delta * decoded_delta;
+                covariance += source_delta * decoded_delta;
+            }
+            source_variance /= sample_count;
+            decoded_variance /= sample_count;
+            covariance /= sample_count;
+
+            ssim_sum += ((2. * source_mean * decoded_mean + SSIM_C1) * (2. * covariance + SSIM_C2))
+                / ((source_mean.powi(2) + decoded_mean.powi(2) + SSIM_C1)
+                    * (source_variance + decoded_variance + SSIM_C2));
+            window_count += 1;
+        }
+    }
+    ssim_sum / window_count.max(1) as f64
+}
+
+#[cfg(test)]
+mod test {
+    use super::{compare_images, QualityReport, VerifyConfig};
+    use crate::source_image::{PixelFormat, SourceImage};
+
+    #[test]
+    fn identical_images_are_perfect() {
+        let pixels = (0..16 * 16).map(|value| value as u8).collect::<Vec<_>>();
+        let source = SourceImage::new(16, 16, PixelFormat::R8, pixels).unwrap();
+        let report = compare_images(&source, &source).unwrap();
+        assert_eq!(report.min_psnr(), f64::INFINITY);
+        assert!((report.min_ssim() - 1.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn thresholds_catch_degraded_images() {
+        let pixels = (0..16 * 16 * 2)
+            .map(|value| value as u8)
+            .collect::<Vec<_>>();
+        let source = SourceImage::new(16, 16, PixelFormat::Rg8, pixels.clone()).unwrap();
+        let degraded_pixels = pixels
+            .iter()
+            .enumerate()
+            .map(|(index, value)| {
+                if index % 2 == 1 {
+                    value / 16 * 16
+                } else {
+                    *value
+                }
+            })
+            .collect();
+        let degraded = SourceImage::new(16, 16, PixelFormat::Rg8, degraded_pixels).unwrap();
+        let report = compare_images(&source, &degraded).unwrap();
+        assert_eq!(report.channels[0].psnr, f64::INFINITY);
+        assert!(report.channels[1].psnr < 40.);
+
+        let strict = VerifyConfig {
+            min_psnr: Some(40.),
+            min_ssim: None,
+        };
+        assert!(strict.check(&report).is_err());
+        let lenient = VerifyConfig {
+            min_psnr: Some(20.),
+            min_ssim: Some(0.5),
+        };
+        assert!(lenient.check(&report).is_ok());
+        assert!(VerifyConfig::default()
+            .check(&QualityReport { channels: vec![] })
+            .is_ok());
+    }
+}
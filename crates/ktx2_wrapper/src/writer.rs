@@ -0,0 +1,682 @@
+//! Pure Rust KTX2 writer for uncompressed textures and textures supercompressed with `ZLib` or
+//! `Zstd`. It needs neither libktx nor a C++ toolchain, and lays the file out the way libktx does:
+//! header, level index, data format descriptor, key/value data, then the levels from smallest to
+//! largest
+
+use std::{collections::BTreeMap, io::Write, path::Path};
+
+use flate2::{write::ZlibEncoder, Compression};
+use serde::{de::IgnoredAny, Deserialize};
+
+#[cfg(feature = "libktx")]
+use crate::config::{CompressionConfig, KTXCompressionConfig, KTXCompressionConfigTypes};
+use crate::{
+    color_space::ColorSpace,
+    deflation::{ZLib, Zstd},
+    dfd,
+    error::{KtxError, KtxStage},
+    metadata::{
+        self, MetadataConfig, MetadataValue, KTX_ORIENTATION_KEY, KTX_SWIZZLE_KEY, KTX_WRITER_KEY,
+        PREMULTIPLIED_KEY, SOURCE_PATH_KEY,
+    },
+    mipmaps::{generate_source_mip_chain, MipmapConfig},
+    source_image::{PixelFormat, SourceImage},
+    texture_shape::TextureShape,
+};
+
+pub const KTX2_IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+/// Identifier, header and section index, the level index follows right after
+const HEADER_LENGTH: usize = 80;
+const LEVEL_INDEX_ENTRY_LENGTH: usize = 24;
+/// Word of the data format descriptor holding bytesPlane0 to bytesPlane3
+const BYTES_PLANE_WORD: usize = 5;
+
+const KTX_SS_NONE: u32 = 0;
+const KTX_SS_ZSTD: u32 = 2;
+const KTX_SS_ZLIB: u32 = 3;
+
+/// Supercompression applied to every level
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WriterSupercompression {
+    #[default]
+    None,
+    /// `level` goes from 1 to 9
+    ZLib { level: u32 },
+    /// `level` goes from 1 to 22
+    Zstd { level: u32 },
+}
+
+impl WriterSupercompression {
+    fn scheme(&self) -> u32 {
+        match self {
+            Self::None => KTX_SS_NONE,
+            Self::ZLib { .. } => KTX_SS_ZLIB,
+            Self::Zstd { .. } => KTX_SS_ZSTD,
+        }
+    }
+
+    fn validate(&self) -> Result<(), KtxError> {
+        match self {
+            Self::ZLib { level } if !(1..=9).contains(level) => Err(KtxError::invalid_config(
+                format!("ZLib level {level} is outside of 1 to 9"),
+            )),
+            Self::Zstd { level } if !(1..=22).contains(level) => Err(KtxError::invalid_config(
+                format!("Zstd level {level} is outside of 1 to 22"),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn compress(&self, level_data: Vec<u8>) -> Result<Vec<u8>, KtxError> {
+        let compress_error = |err| KtxError::Io {
+            stage: KtxStage::Compress,
+            path: None,
+            source: err,
+        };
+        match self {
+            Self::None => Ok(level_data),
+            Self::ZLib { level } => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::new(*level));
+                encoder.write_all(&level_data).map_err(compress_error)?;
+                encoder.finish().map_err(compress_error)
+            }
+            Self::Zstd { level } => {
+                zstd::bulk::compress(&level_data, *level as i32).map_err(compress_error)
+            }
+        }
+    }
+}
+
+/// Settings of the Rust writer, the part of `CompressionConfig` it can honour. It deserializes from
+/// the JSON of a `ZLib` or `Zstd` `CompressionConfig`, so builds without libktx take the same configs
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(try_from = "WriterConfigJson")]
+pub struct WriterConfig {
+    pub supercompression: WriterSupercompression,
+    pub color_space: ColorSpace,
+    pub premultiply: bool,
+    pub mipmaps: Option<MipmapConfig>,
+    pub metadata: Option<MetadataConfig>,
+}
+
+/// The fields of a `CompressionConfig` the writer reads. `channels` and `verify` are ignored, the
+/// channels are picked when the source image is decoded
+#[derive(Deserialize)]
+struct WriterConfigJson {
+    config_type: WriterConfigType,
+    config: WriterCodec,
+    #[serde(default = "default_premultiply")]
+    premultiply: Option<bool>,
+    #[serde(default)]
+    color_space: ColorSpace,
+    #[serde(default)]
+    mipmaps: Option<MipmapConfig>,
+    #[serde(default)]
+    supercompression: Option<IgnoredAny>,
+    #[serde(default)]
+    metadata: Option<MetadataConfig>,
+}
+
+fn default_premultiply() -> Option<bool> {
+    Some(true)
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq, strum::Display)]
+enum WriterConfigType {
+    ZLib,
+    Zstd,
+}
+
+#[derive(Deserialize)]
+enum WriterCodec {
+    ZLib(ZLib),
+    Zstd(Zstd),
+}
+
+impl TryFrom<WriterConfigJson> for WriterConfig {
+    type Error = String;
+
+    fn try_from(json: WriterConfigJson) -> Result<Self, Self::Error> {
+        let (config_discriminant, supercompression) = match json.config {
+            WriterCodec::ZLib(zlib) => (
+                WriterConfigType::ZLib,
+                WriterSupercompression::ZLib {
+                    level: zlib.deflation_value.into(),
+                },
+            ),
+            WriterCodec::Zstd(zstd) => (
+                WriterConfigType::Zstd,
+                WriterSupercompression::Zstd {
+                    level: zstd.deflation_value.into(),
+                },
+            ),
+        };
+        if config_discriminant != json.config_type {
+            return Err(format!(
+                "config_type {} does not match the {config_discriminant} config",
+                json.config_type
+            ));
+        }
+        if json.supercompression.is_some() {
+            return Err(format!(
+                "supercompression cannot follow {config_discriminant}, a texture can only be supercompressed once"
+            ));
+        }
+        Ok(Self {
+            supercompression,
+            color_space: json.color_space,
+            premultiply: json.premultiply.unwrap_or(true),
+            mipmaps: json.mipmaps,
+            metadata: json.metadata,
+        })
+    }
+}
+
+#[cfg(feature = "libktx")]
+impl TryFrom<&CompressionConfig> for WriterConfig {
+    type Error = KtxError;
+
+    /// Only `ZLib` and `Zstd` configs can be written without libktx
+    fn try_from(config: &CompressionConfig) -> Result<Self, Self::Error> {
+        config
+            .validate()
+            .map_err(|err| KtxError::invalid_config(err.to_string()))?;
+        let supercompression = match &config.config {
+            KTXCompressionConfig::ZLib(zlib) => WriterSupercompression::ZLib {
+                level: zlib.deflation_value.into(),
+            },
+            KTXCompressionConfig::Zstd(zstd) => WriterSupercompression::Zstd {
+                level: zstd.deflation_value.into(),
+            },
+            other => {
+                return Err(KtxError::invalid_config(format!(
+                    "{} needs libktx, the Rust writer only handles ZLib and Zstd",
+                    KTXCompressionConfigTypes::from(other)
+                )))
+            }
+        };
+        Ok(Self {
+            supercompression,
+            color_space: config.color_space,
+            premultiply: config.premultiply.unwrap_or(true),
+            mipmaps: config.mipmaps.clone(),
+            metadata: config.metadata.clone(),
+        })
+    }
+}
+
+/// Writes a 2D texture and returns the KTX2 file
+pub fn write_texture(
+    source_image: SourceImage,
+    config: &WriterConfig,
+) -> Result<Vec<u8>, KtxError> {
+    write_texture_with_shape(vec![source_image], TextureShape::Texture2D, config, &[])
+}
+
+/// Writes a texture of any shape and returns the KTX2 file. `source_images` are ordered like for
+/// `encode_texture_with_shape`, and `image_input_paths` are only used for the `SourcePath` key
+pub fn write_texture_with_shape(
+    mut source_images: Vec<SourceImage>,
+    shape: TextureShape,
+    config: &WriterConfig,
+    image_input_paths: &[&Path],
+) -> Result<Vec<u8>, KtxError> {
+    config.supercompression.validate()?;
+    if source_images.len() != shape.image_count() as usize {
+        return Err(KtxError::invalid_config(format!(
+            "{shape:?} needs {} images but {} were given",
+            shape.image_count(),
+            source_images.len()
+        )));
+    }
+    let mipmap_config = config
+        .mipmaps
+        .as_ref()
+        .filter(|mipmap_config| mipmap_config.enabled);
+    if mipmap_config.is_some() && matches!(shape, TextureShape::Volume { .. }) {
+        return Err(KtxError::invalid_config(
+            "Mipmap generation is not supported for volume textures",
+        ));
+    }
+
+    let (width, height, pixel_format) = (
+        source_images[0].width,
+        source_images[0].height,
+        source_images[0].pixel_format,
+    );
+    if let Some(image_index) = source_images.iter().position(|source_image| {
+        (
+            source_image.width,
+            source_image.height,
+            source_image.pixel_format,
+        ) != (width, height, pixel_format)
+    }) {
+        return Err(KtxError::invalid_config(format!(
+            "Image {image_index} does not match the first image, which is {width}x{height} {pixel_format:?}"
+        )));
+    }
+    if shape.num_faces() == 6 && width != height {
+        return Err(KtxError::invalid_config(format!(
+            "Cubemap faces must be square but are {width}x{height}"
+        )));
+    }
+    let color_space = pixel_format.effective_color_space(config.color_space);
+
+    let level_count =
+        mipmap_config.map_or(1, |mipmap_config| mipmap_config.level_count(width, height)) as usize;
+    let mut levels = vec![Vec::new(); level_count];
+    for source_image in &mut source_images {
+        if config.premultiply {
+            source_image.premultiply_alpha(color_space);
+        }
+        let mip_levels = match mipmap_config {
+            Some(mipmap_config) => {
+                generate_source_mip_chain(source_image, mipmap_config, color_space)
+            }
+            None => vec![],
+        };
+        levels[0].extend_from_slice(&source_image.data);
+        for (level, level_data) in mip_levels.into_iter().enumerate() {
+            levels[level + 1].extend(level_data);
+        }
+    }
+    let uncompressed_lengths = levels.iter().map(Vec::len).collect::<Vec<_>>();
+    let levels = levels
+        .into_iter()
+        .map(|level_data| config.supercompression.compress(level_data))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let alpha_premultiplied = config.premultiply && pixel_format.has_alpha();
+    let mut dfd = dfd::uncompressed_descriptor(pixel_format, color_space.transfer_function());
+    dfd::set_alpha_premultiplied(&mut dfd, alpha_premultiplied);
+    if config.supercompression != WriterSupercompression::None {
+        // The KTX2 spec requires bytesPlane0 to be 0 once the levels are supercompressed
+        dfd[BYTES_PLANE_WORD] = 0;
+    }
+    let dfd = dfd
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect::<Vec<_>>();
+    let kvd = key_value_data(&key_values(config, image_input_paths, alpha_premultiplied)?);
+
+    let dfd_offset = HEADER_LENGTH + level_count * LEVEL_INDEX_ENTRY_LENGTH;
+    let kvd_offset = dfd_offset + dfd.len();
+    // Supercompressed levels are not aligned, the others are aligned to both the texel size and 4
+    let level_alignment = match config.supercompression {
+        WriterSupercompression::None => lcm(pixel_format.bytes_per_pixel(), 4),
+        _ => 1,
+    };
+    let mut level_offsets = vec![0; level_count];
+    let mut file_length = kvd_offset + kvd.len();
+    for level in (0..level_count).rev() {
+        file_length = file_length.next_multiple_of(level_alignment);
+        level_offsets[level] = file_length;
+        file_length += levels[level].len();
+    }
+
+    let mut ktx_bytes = Vec::with_capacity(file_length);
+    ktx_bytes.extend_from_slice(&KTX2_IDENTIFIER);
+    let header = [
+        pixel_format_vk_format(pixel_format, color_space),
+        pixel_format.bytes_per_sample() as u32,
+        width,
+        height,
+        match shape {
+            TextureShape::Volume { .. } => shape.depth(),
+            _ => 0,
+        },
+        if shape.is_array() {
+            shape.num_layers()
+        } else {
+            0
+        },
+        shape.num_faces(),
+        level_count as u32,
+        config.supercompression.scheme(),
+        dfd_offset as u32,
+        dfd.len() as u32,
+        if kvd.is_empty() { 0 } else { kvd_offset as u32 },
+        kvd.len() as u32,
+    ];
+    for value in header {
+        ktx_bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    // No supercompression global data
+    ktx_bytes.extend_from_slice(&[0; 16]);
+    for level in 0..level_count {
+        for value in [
+            level_offsets[level],
+            levels[level].len(),
+            uncompressed_lengths[level],
+        ] {
+            ktx_bytes.extend_from_slice(&(value as u64).to_le_bytes());
+        }
+    }
+    ktx_bytes.extend_from_slice(&dfd);
+    ktx_bytes.extend_from_slice(&kvd);
+    for level in (0..level_count).rev() {
+        ktx_bytes.resize(level_offsets[level], 0);
+        ktx_bytes.extend_from_slice(&levels[level]);
+    }
+    Ok(ktx_bytes)
+}
+
+/// The key/value pairs `config.metadata` declares, plus `KTXwriter` which is always written.
+/// `Premultiplied` matches the flag of the data format descriptor
+fn key_values(
+    config: &WriterConfig,
+    image_input_paths: &[&Path],
+    alpha_premultiplied: bool,
+) -> Result<BTreeMap<String, MetadataValue>, KtxError> {
+    let mut key_values = BTreeMap::new();
+    let default_writer = concat!("ktx2_wrapper ", env!("CARGO_PKG_VERSION"));
+    let Some(metadata_config) = &config.metadata else {
+        key_values.insert(KTX_WRITER_KEY.to_string(), default_writer.into());
+        return Ok(key_values);
+    };
+
+    if let Some((key, _)) = metadata_config
+        .custom
+        .iter()
+        .find(|(key, _)| metadata::is_reserved_key(key))
+    {
+        return Err(KtxError::invalid_config(format!(
+            "custom metadata key {key} is reserved"
+        )));
+    }
+    key_values.extend(metadata_config.custom.clone());
+    if let Some(orientation) = &metadata_config.orientation {
+        if !metadata::is_valid_orientation(orientation) {
+            return Err(KtxError::invalid_config(format!(
+                "{orientation} is not a valid orientation"
+            )));
+        }
+        key_values.insert(KTX_ORIENTATION_KEY.to_string(), orientation.as_str().into());
+    }
+    if let Some(swizzle) = &metadata_config.swizzle {
+        if !metadata::is_valid_swizzle(swizzle) {
+            return Err(KtxError::invalid_config(format!(
+                "{swizzle} is not a valid swizzle"
+            )));
+        }
+        key_values.insert(KTX_SWIZZLE_KEY.to_string(), swizzle.as_str().into());
+    }
+    let writer = metadata_config.writer.as_deref().unwrap_or(default_writer);
+    key_values.insert(KTX_WRITER_KEY.to_string(), writer.into());
+    if metadata_config.source_path && !image_input_paths.is_empty() {
+        let source_paths = image_input_paths
+            .iter()
+            .map(|image_input_path| image_input_path.display().to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        key_values.insert(
+            SOURCE_PATH_KEY.to_string(),
+            MetadataValue::String(source_paths),
+        );
+    }
+    if metadata_config.premultiplied {
+        key_values.insert(
+            PREMULTIPLIED_KEY.to_string(),
+            MetadataValue::String(alpha_premultiplied.to_string()),
+        );
+    }
+    Ok(key_values)
+}
+
+/// Serializes key/value pairs sorted by key, each one prefixed by its length and padded to 4 bytes
+fn key_value_data(key_values: &BTreeMap<String, MetadataValue>) -> Vec<u8> {
+    let mut kvd = Vec::new();
+    for (key, value) in key_values {
+        let value = value.to_bytes();
+        let key_and_value_length = key.len() + 1 + value.len();
+        kvd.extend_from_slice(&(key_and_value_length as u32).to_le_bytes());
+        kvd.extend_from_slice(key.as_bytes());
+        kvd.push(0);
+        kvd.extend_from_slice(&value);
+        kvd.resize(kvd.len().next_multiple_of(4), 0);
+    }
+    kvd
+}
+
+/// `VkFormat` values of the formats `SourceImage` can hold
+fn pixel_format_vk_format(pixel_format: PixelFormat, color_space: ColorSpace) -> u32 {
+    let srgb = color_space == ColorSpace::Srgb;
+    match pixel_format {
+        PixelFormat::R8 if srgb => 15,
+        PixelFormat::R8 => 9,
+        PixelFormat::Rg8 if srgb => 22,
+        PixelFormat::Rg8 => 16,
+        PixelFormat::Rgb8 if srgb => 29,
+        PixelFormat::Rgb8 => 23,
+        PixelFormat::Rgba8 if srgb => 43,
+        PixelFormat::Rgba8 => 37,
+        PixelFormat::Rgba16 => 91,
+        PixelFormat::Rgba16Float => 97,
+    }
+}
+
+fn lcm(a: usize, b: usize) -> usize {
+    let (mut x, mut y) = (a, b);
+    while y != 0 {
+        (x, y) = (y, x % y);
+    }
+    a / x * b
+}
+
+#[cfg(test)]
+mod test {
+    use super::{key_values, write_texture, WriterConfig, WriterSupercompression, KTX2_IDENTIFIER};
+    #[cfg(feature = "libktx")]
+    use super::{HEADER_LENGTH, LEVEL_INDEX_ENTRY_LENGTH};
+    #[cfg(feature = "libktx")]
+    use crate::metadata::KTX_WRITER_KEY;
+    use crate::{
+        color_space::ColorSpace,
+        metadata::{MetadataConfig, MetadataValue, PREMULTIPLIED_KEY},
+        source_image::SourceImage,
+    };
+
+    fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn read_u64(bytes: &[u8], offset: usize) -> usize {
+        u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap()) as usize
+    }
+
+    #[test]
+    fn writes_header_index_and_supercompressed_levels() {
+        let pixels = (0..4 * 2 * 4).map(|value| value as u8).collect::<Vec<_>>();
+        let source_image = SourceImage::from_rgba8(4, 2, pixels.clone()).unwrap();
+        let config = WriterConfig {
+            supercompression: WriterSupercompression::Zstd { level: 3 },
+            ..Default::default()
+        };
+        let ktx_bytes = write_texture(source_image, &config).unwrap();
+
+        assert_eq!(ktx_bytes[..12], KTX2_IDENTIFIER);
+        assert_eq!(read_u32(&ktx_bytes, 12), 37);
+        assert_eq!(read_u32(&ktx_bytes, 20), 4);
+        assert_eq!(read_u32(&ktx_bytes, 24), 2);
+        assert_eq!(read_u32(&ktx_bytes, 36), 1);
+        assert_eq!(read_u32(&ktx_bytes, 40), 1);
+        assert_eq!(read_u32(&ktx_bytes, 44), 2);
+
+        let dfd_offset = read_u32(&ktx_bytes, 48) as usize;
+        assert_eq!(dfd_offset, 80 + 24);
+        assert_eq!(read_u32(&ktx_bytes, 52), read_u32(&ktx_bytes, dfd_offset));
+
+        let (level_offset, level_length) = (read_u64(&ktx_bytes, 80), read_u64(&ktx_bytes, 88));
+        assert_eq!(read_u64(&ktx_bytes, 96), pixels.len());
+        assert_eq!(level_offset + level_length, ktx_bytes.len());
+        let level_data = zstd::bulk::decompress(
+            &ktx_bytes[level_offset..level_offset + level_length],
+            pixels.len(),
+        )
+        .unwrap();
+        assert_eq!(level_data, pixels);
+    }
+
+    #[test]
+    fn premultiplied_metadata_follows_the_descriptor_flag() {
+        let config = WriterConfig {
+            premultiply: true,
+            metadata: Some(MetadataConfig {
+                premultiplied: true,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        // An RGB texture has no alpha to premultiply, even though the config asks for it
+        let key_values = key_values(&config, &[], false).unwrap();
+        assert_eq!(
+            key_values.get(PREMULTIPLIED_KEY),
+            Some(&MetadataValue::String("false".to_string()))
+        );
+    }
+
+    #[test]
+    fn reads_the_json_of_zlib_and_zstd_compression_configs() {
+        let config: WriterConfig = serde_json::from_str(
+            r#"{
+                "config_type": "Zstd",
+                "config": { "Zstd": { "deflation_value": 30 } },
+                "premultiply": false,
+                "color_space": "Srgb",
+                "mipmaps": { "enabled": true },
+                "channels": "Auto"
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.supercompression,
+            WriterSupercompression::Zstd { level: 22 }
+        );
+        assert_eq!(config.color_space, ColorSpace::Srgb);
+        assert!(!config.premultiply);
+        assert!(config.mipmaps.is_some());
+
+        let config: WriterConfig = serde_json::from_str(
+            r#"{ "config_type": "ZLib", "config": { "ZLib": { "deflation_value": 5 } } }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.supercompression,
+            WriterSupercompression::ZLib { level: 5 }
+        );
+        assert!(config.premultiply);
+
+        for rejected_json in [
+            r#"{ "config_type": "BasisUniversalUASTC", "config": { "BasisUniversalUASTC": {} } }"#,
+            r#"{ "config_type": "ZLib", "config": { "Zstd": { "deflation_value": 5 } } }"#,
+            r#"{
+                "config_type": "ZLib",
+                "config": { "ZLib": { "deflation_value": 5 } },
+                "supercompression": { "Zstd": { "deflation_value": 5 } }
+            }"#,
+        ] {
+            assert!(serde_json::from_str::<WriterConfig>(rejected_json).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_out_of_range_levels() {
+        let source_image = SourceImage::from_rgba8(1, 1, vec![0; 4]).unwrap();
+        let config = WriterConfig {
+            supercompression: WriterSupercompression::ZLib { level: 12 },
+            ..Default::default()
+        };
+        assert!(write_texture(source_image, &config).is_err());
+    }
+
+    /// Entries of the level index with offsets made relative to the first level, since the levels
+    /// move with the length of the key/value data. Supercompressed levels are not padded
+    #[cfg(feature = "libktx")]
+    fn relative_level_index(ktx_bytes: &[u8]) -> Vec<[usize; 3]> {
+        let level_count = read_u32(ktx_bytes, 40).max(1) as usize;
+        let level_index = (0..level_count)
+            .map(|level| {
+                let entry_offset = HEADER_LENGTH + level * LEVEL_INDEX_ENTRY_LENGTH;
+                [0, 8, 16].map(|field_offset| read_u64(ktx_bytes, entry_offset + field_offset))
+            })
+            .collect::<Vec<_>>();
+        let first_level_offset = level_index.iter().map(|entry| entry[0]).min().unwrap();
+        level_index
+            .into_iter()
+            .map(|[offset, length, uncompressed_length]| {
+                [offset - first_level_offset, length, uncompressed_length]
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "libktx")]
+    fn levels(ktx_bytes: &[u8]) -> Vec<&[u8]> {
+        let level_count = read_u32(ktx_bytes, 40).max(1) as usize;
+        (0..level_count)
+            .map(|level| {
+                let entry_offset = HEADER_LENGTH + level * LEVEL_INDEX_ENTRY_LENGTH;
+                let offset = read_u64(ktx_bytes, entry_offset);
+                &ktx_bytes[offset..offset + read_u64(ktx_bytes, entry_offset + 8)]
+            })
+            .collect()
+    }
+
+    /// Every raw key/value entry but `KTXwriter`, which names the library that wrote the file
+    #[cfg(feature = "libktx")]
+    fn key_values_without_writer(ktx_bytes: &[u8]) -> Vec<&[u8]> {
+        let kvd_offset = read_u32(ktx_bytes, 56) as usize;
+        let kvd_end = kvd_offset + read_u32(ktx_bytes, 60) as usize;
+        let mut entries = vec![];
+        let mut entry_offset = kvd_offset;
+        while entry_offset < kvd_end {
+            let entry_length = read_u32(ktx_bytes, entry_offset) as usize;
+            let entry = &ktx_bytes[entry_offset + 4..entry_offset + 4 + entry_length];
+            if !entry.starts_with(format!("{KTX_WRITER_KEY}\0").as_bytes()) {
+                entries.push(entry);
+            }
+            entry_offset += (4 + entry_length).next_multiple_of(4);
+        }
+        entries
+    }
+
+    #[cfg(feature = "libktx")]
+    #[test]
+    fn writer_output_matches_libktx_byte_for_byte() {
+        use crate::{config::CompressionConfig, encode_texture};
+
+        let config: CompressionConfig = serde_json::from_str(
+            r#"{
+                "config_type": "Zstd",
+                "config": { "Zstd": { "deflation_value": 5 } },
+                "mipmaps": { "enabled": true },
+                "metadata": { "orientation": "rd", "premultiplied": true }
+            }"#,
+        )
+        .unwrap();
+        let pixels = (0..8 * 8 * 4).map(|value| value as u8).collect::<Vec<_>>();
+        let source_image = SourceImage::from_rgba8(8, 8, pixels).unwrap();
+
+        let libktx_bytes = encode_texture(source_image.clone(), &config).unwrap();
+        let writer_config = WriterConfig::try_from(&config).unwrap();
+        let writer_bytes = write_texture(source_image, &writer_config).unwrap();
+
+        // From the identifier up to supercompressionScheme
+        assert_eq!(libktx_bytes[..48], writer_bytes[..48]);
+        let dfd = |ktx_bytes: &[u8]| {
+            let dfd_offset = read_u32(ktx_bytes, 48) as usize;
+            ktx_bytes[dfd_offset..dfd_offset + read_u32(ktx_bytes, 52) as usize].to_vec()
+        };
+        assert_eq!(dfd(&libktx_bytes), dfd(&writer_bytes));
+        assert_eq!(
+            key_values_without_writer(&libktx_bytes),
+            key_values_without_writer(&writer_bytes)
+        );
+        assert_eq!(
+            relative_level_index(&libktx_bytes),
+            relative_level_index(&writer_bytes)
+        );
+        assert_eq!(levels(&libktx_bytes), levels(&writer_bytes));
+    }
+}
@@ -12,6 +12,8 @@ pub type LocalError = Box<dyn Error + Send + Sync>;
 pub enum SupportedImages {
     Png,
     Jpeg,
+    Hdr,
+    Exr,
 }
 
 impl SupportedImages {
@@ -34,6 +36,8 @@ impl SupportedImages {
         match potential_image_str.to_ascii_lowercase().to_str() {
             Some("png") => Ok(SupportedImages::Png),
             Some("jpeg" | "jpg") => Ok(SupportedImages::Jpeg),
+            Some("hdr") => Ok(SupportedImages::Hdr),
+            Some("exr") => Ok(SupportedImages::Exr),
             Some(unknown_extension) => {
                 Err(format!("Unsupported image type {unknown_extension} found").into())
             }